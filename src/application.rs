@@ -1,13 +1,19 @@
 use crate::{
-    config,
+    config, dbus,
     photo::provider::{Config, FailedFiles},
+    remote::RemoteCommand,
+    spawn,
     window::PpfWindow,
 };
 use gtk::gdk::Display;
+use gtk::glib::{clone, VariantTy};
 use gtk::CssProvider;
 use gtk::STYLE_PROVIDER_PRIORITY_APPLICATION;
 use gtk::{gio, glib, prelude::*, subclass::prelude::*};
 use std::cell::RefCell;
+use std::path::PathBuf;
+use tokio::sync::mpsc::{self, UnboundedReceiver, UnboundedSender};
+use tracing::warn;
 
 mod imp {
     use super::*;
@@ -16,6 +22,10 @@ mod imp {
     pub struct PpfApplication {
         pub(super) config: RefCell<Config>,
         pub(super) failed_files: RefCell<FailedFiles>,
+        pub(super) window: glib::WeakRef<PpfWindow>,
+        /// Commands from D-Bus and the application actions
+        pub(super) command_sender: RefCell<Option<UnboundedSender<RemoteCommand>>>,
+        pub(super) command_receiver: RefCell<Option<UnboundedReceiver<RemoteCommand>>>,
     }
 
     #[glib::object_subclass]
@@ -28,13 +38,32 @@ mod imp {
     impl ObjectImpl for PpfApplication {}
     impl ApplicationImpl for PpfApplication {
         fn activate(&self) {
+            // Activating again, e.g. from a second launch, keeps the one window
+            if let Some(window) = self.window.upgrade() {
+                window.present();
+                return;
+            }
+
             let application = self.obj();
             let window = PpfWindow::new(
                 &*application,
                 self.config.borrow().clone(),
                 self.failed_files.borrow().clone(),
             );
+            self.window.set(Some(&window));
             window.present();
+
+            if let Some(mut receiver) = self.command_receiver.take() {
+                let window = window.downgrade();
+                spawn!(async move {
+                    while let Some(command) = receiver.recv().await {
+                        match window.upgrade() {
+                            Some(window) => window.handle_command(command),
+                            None => break,
+                        }
+                    }
+                });
+            }
         }
 
         fn startup(&self) {
@@ -48,6 +77,21 @@ mod imp {
                 &provider,
                 STYLE_PROVIDER_PRIORITY_APPLICATION,
             );
+
+            let (sender, receiver) = mpsc::unbounded_channel();
+            self.command_receiver.replace(Some(receiver));
+            self.command_sender.replace(Some(sender.clone()));
+            self.obj().setup_actions();
+
+            let config = self.config.borrow();
+            if let Some(connection) = self.obj().dbus_connection() {
+                if let Err(e) = dbus::register(&connection, &config, sender.clone()) {
+                    warn!("Failed to export the D-Bus interface, {}", e);
+                }
+            }
+            if config.dbus_system_bus {
+                dbus::own_system_name(&config, sender);
+            }
         }
     }
 
@@ -72,4 +116,44 @@ impl PpfApplication {
 
         obj
     }
+
+    fn send_command(&self, command: RemoteCommand) {
+        if let Some(sender) = self.imp().command_sender.borrow().as_ref() {
+            let _ = sender.send(command);
+        }
+    }
+
+    /// Actions for remote activation, e.g.
+    /// `gapplication action com.mcostea.PiPhotoFrame next`.
+    fn setup_actions(&self) {
+        let commands = [
+            ("next", RemoteCommand::Next),
+            ("previous", RemoteCommand::Previous),
+            ("pause", RemoteCommand::SetPaused(true)),
+            ("resume", RemoteCommand::SetPaused(false)),
+        ];
+        for (name, command) in commands {
+            let action = gio::SimpleAction::new(name, None);
+            action.connect_activate(clone!(@weak self as this => move |_, _| {
+                this.send_command(command.clone());
+            }));
+            self.add_action(&action);
+        }
+
+        let show_file = gio::SimpleAction::new("show-file", Some(VariantTy::STRING));
+        show_file.connect_activate(clone!(@weak self as this => move |_, parameter| {
+            if let Some(path) = parameter.and_then(|p| p.get::<String>()) {
+                this.send_command(RemoteCommand::Show(PathBuf::from(path)));
+            }
+        }));
+        self.add_action(&show_file);
+
+        let set_album = gio::SimpleAction::new("set-album", Some(VariantTy::STRING));
+        set_album.connect_activate(clone!(@weak self as this => move |_, parameter| {
+            if let Some(album) = parameter.and_then(|p| p.get::<String>()) {
+                this.send_command(RemoteCommand::Album(Some(album).filter(|a| !a.is_empty())));
+            }
+        }));
+        self.add_action(&set_album);
+    }
 }
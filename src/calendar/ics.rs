@@ -0,0 +1,991 @@
+use std::collections::HashMap;
+
+use gtk::glib::{DateTime, TimeZone};
+use tracing::{debug, warn};
+
+/// Upper bound on the occurrences looked at per event, against runaway rules.
+const MAX_OCCURRENCES: usize = 20_000;
+const DAY: i64 = 86_400;
+
+/// A calendar date without a time zone.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord)]
+pub struct Date {
+    pub year: i32,
+    pub month: u32,
+    pub day: u32,
+}
+
+impl Date {
+    fn from_days(days: i64) -> Self {
+        // Howard Hinnant's civil_from_days
+        let z = days + 719_468;
+        let era = z.div_euclid(146_097);
+        let doe = z.rem_euclid(146_097);
+        let yoe = (doe - doe / 1460 + doe / 36524 - doe / 146_096) / 365;
+        let doy = doe - (365 * yoe + yoe / 4 - yoe / 100);
+        let mp = (5 * doy + 2) / 153;
+        let day = (doy - (153 * mp + 2) / 5 + 1) as u32;
+        let month = (if mp < 10 { mp + 3 } else { mp - 9 }) as u32;
+        let year = (yoe + era * 400 + if month <= 2 { 1 } else { 0 }) as i32;
+        Date { year, month, day }
+    }
+
+    /// Days since 1970-01-01.
+    fn days(&self) -> i64 {
+        let year = self.year as i64 - if self.month <= 2 { 1 } else { 0 };
+        let era = year.div_euclid(400);
+        let yoe = year.rem_euclid(400);
+        let month = self.month as i64;
+        let doy =
+            (153 * (if month > 2 { month - 3 } else { month + 9 }) + 2) / 5 + self.day as i64 - 1;
+        let doe = yoe * 365 + yoe / 4 - yoe / 100 + doy;
+        era * 146_097 + doe - 719_468
+    }
+
+    /// 0 is Monday.
+    fn weekday(&self) -> u32 {
+        (self.days() + 3).rem_euclid(7) as u32
+    }
+
+    fn is_valid(&self) -> bool {
+        (1..=12).contains(&self.month)
+            && self.day >= 1
+            && self.day <= days_in_month(self.year, self.month)
+    }
+
+    fn add_months(&self, months: i64) -> Date {
+        let index = self.year as i64 * 12 + self.month as i64 - 1 + months;
+        Date {
+            year: index.div_euclid(12) as i32,
+            month: index.rem_euclid(12) as u32 + 1,
+            day: self.day,
+        }
+    }
+}
+
+fn days_in_month(year: i32, month: u32) -> u32 {
+    match month {
+        1 | 3 | 5 | 7 | 8 | 10 | 12 => 31,
+        4 | 6 | 9 | 11 => 30,
+        _ if (year % 4 == 0 && year % 100 != 0) || year % 400 == 0 => 29,
+        _ => 28,
+    }
+}
+
+#[derive(Debug, Clone, PartialEq)]
+enum Zone {
+    Utc,
+    /// No time zone, shown at the same wall clock time everywhere
+    Floating,
+    Named(String),
+}
+
+/// A DATE or DATE-TIME value.
+#[derive(Debug, Clone, PartialEq)]
+struct Stamp {
+    date: Date,
+    /// `None` for all-day dates
+    time: Option<(u32, u32, u32)>,
+    zone: Zone,
+}
+
+impl Stamp {
+    fn parse(value: &str, params: &HashMap<String, String>) -> Option<Self> {
+        let value = value.trim();
+        let number = |range: std::ops::Range<usize>| value.get(range)?.parse::<u32>().ok();
+        let date = Date {
+            year: number(0..4)? as i32,
+            month: number(4..6)?,
+            day: number(6..8)?,
+        };
+        if !date.is_valid() {
+            return None;
+        }
+
+        if value.len() == 8 || params.get("VALUE").is_some_and(|v| v == "DATE") {
+            return Some(Stamp {
+                date,
+                time: None,
+                zone: Zone::Floating,
+            });
+        }
+
+        if value.get(8..9)? != "T" {
+            return None;
+        }
+        let time = (number(9..11)?, number(11..13)?, number(13..15)?);
+        let zone = if value.ends_with('Z') {
+            Zone::Utc
+        } else if let Some(tzid) = params.get("TZID") {
+            Zone::Named(tzid.clone())
+        } else {
+            Zone::Floating
+        };
+
+        Some(Stamp {
+            date,
+            time: Some(time),
+            zone,
+        })
+    }
+
+    fn with_date(&self, date: Date) -> Stamp {
+        Stamp {
+            date,
+            time: self.time,
+            zone: self.zone.clone(),
+        }
+    }
+
+    fn to_unix(&self, zones: &mut TimeZones) -> Option<i64> {
+        let (hour, minute, second) = self.time.unwrap_or((0, 0, 0));
+        DateTime::new(
+            &zones.get(&self.zone),
+            self.date.year,
+            self.date.month as i32,
+            self.date.day as i32,
+            hour as i32,
+            minute as i32,
+            second as f64,
+        )
+        .ok()
+        .map(|date_time| date_time.to_unix())
+    }
+}
+
+/// Resolves TZID values to system time zones, once per name.
+#[derive(Default)]
+pub struct TimeZones {
+    zones: HashMap<String, TimeZone>,
+}
+
+impl TimeZones {
+    fn get(&mut self, zone: &Zone) -> TimeZone {
+        let tzid = match zone {
+            Zone::Utc => return TimeZone::utc(),
+            Zone::Floating => return TimeZone::local(),
+            Zone::Named(tzid) => tzid,
+        };
+
+        self.zones
+            .entry(tzid.clone())
+            .or_insert_with(|| {
+                // Some calendars prefix the name, e.g. "/mozilla.org/20050126_1/Europe/Berlin"
+                let parts: Vec<&str> = tzid.split('/').filter(|p| !p.is_empty()).collect();
+                let candidates = [
+                    tzid.clone(),
+                    parts[parts.len().saturating_sub(2)..].join("/"),
+                ];
+                for candidate in candidates {
+                    let timezone = TimeZone::new(Some(candidate.as_str()));
+                    if timezone.identifier() == candidate.as_str() {
+                        return timezone;
+                    }
+                }
+
+                warn!("Unknown time zone {}, using local time", tzid);
+                TimeZone::local()
+            })
+            .clone()
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+enum Frequency {
+    Daily,
+    Weekly,
+    Monthly,
+    Yearly,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+struct Rule {
+    frequency: Frequency,
+    interval: u32,
+    count: Option<u32>,
+    until: Option<Stamp>,
+    /// Ordinal (0 for every one) and weekday (0 is Monday), e.g. -1FR
+    by_day: Vec<(i32, u32)>,
+    by_month_day: Vec<i32>,
+    by_month: Vec<u32>,
+}
+
+impl Rule {
+    fn parse(value: &str) -> Option<Self> {
+        let mut rule = Rule {
+            frequency: Frequency::Daily,
+            interval: 1,
+            count: None,
+            until: None,
+            by_day: vec![],
+            by_month_day: vec![],
+            by_month: vec![],
+        };
+        let mut frequency = None;
+
+        for part in value.split(';') {
+            let Some((key, value)) = part.split_once('=') else {
+                continue;
+            };
+            match key.to_ascii_uppercase().as_str() {
+                "FREQ" => {
+                    frequency = match value.to_ascii_uppercase().as_str() {
+                        "DAILY" => Some(Frequency::Daily),
+                        "WEEKLY" => Some(Frequency::Weekly),
+                        "MONTHLY" => Some(Frequency::Monthly),
+                        "YEARLY" => Some(Frequency::Yearly),
+                        other => {
+                            warn!("Unsupported recurrence frequency {}", other);
+                            return None;
+                        }
+                    }
+                }
+                "INTERVAL" => rule.interval = value.parse().ok().filter(|i| *i > 0)?,
+                "COUNT" => rule.count = value.parse().ok(),
+                "UNTIL" => rule.until = Stamp::parse(value, &HashMap::new()),
+                "BYDAY" => {
+                    rule.by_day = value.split(',').filter_map(parse_weekday).collect();
+                }
+                "BYMONTHDAY" => {
+                    rule.by_month_day = value.split(',').filter_map(|d| d.parse().ok()).collect();
+                }
+                "BYMONTH" => {
+                    rule.by_month = value.split(',').filter_map(|m| m.parse().ok()).collect();
+                }
+                "WKST" => {}
+                other => debug!("Ignoring recurrence part {}", other),
+            }
+        }
+
+        rule.frequency = frequency?;
+        Some(rule)
+    }
+
+    /// Dates of one period (day, week, month or year) that match the rule,
+    /// in order. `first` is the first day of the period.
+    fn period_dates(&self, first: Date, start: &Date) -> Vec<Date> {
+        let mut dates = match self.frequency {
+            Frequency::Daily => vec![first],
+            Frequency::Weekly => {
+                let weekdays: Vec<u32> = if self.by_day.is_empty() {
+                    vec![start.weekday()]
+                } else {
+                    self.by_day.iter().map(|(_, weekday)| *weekday).collect()
+                };
+                let monday = first.days();
+                let mut dates: Vec<Date> = weekdays
+                    .iter()
+                    .map(|weekday| Date::from_days(monday + *weekday as i64))
+                    .collect();
+                dates.sort();
+                dates.dedup();
+                dates
+            }
+            Frequency::Monthly => self.month_dates(first.year, first.month, start),
+            // BYDAY counts weekdays of the whole year without BYMONTH, e.g. 20MO
+            Frequency::Yearly if self.by_month.is_empty() && !self.by_day.is_empty() => {
+                self.year_dates(first.year)
+            }
+            Frequency::Yearly => {
+                let months = if !self.by_month.is_empty() {
+                    self.by_month.clone()
+                } else if !self.by_month_day.is_empty() {
+                    (1..=12).collect()
+                } else {
+                    vec![start.month]
+                };
+                let mut dates: Vec<Date> = months
+                    .iter()
+                    .flat_map(|month| self.month_dates(first.year, *month, start))
+                    .collect();
+                dates.sort();
+                dates
+            }
+        };
+
+        // BYDAY and BYMONTH limit daily rules, and BYMONTH limits monthly ones
+        if self.frequency == Frequency::Daily && !self.by_day.is_empty() {
+            dates.retain(|date| self.by_day.iter().any(|(_, w)| *w == date.weekday()));
+        }
+        if matches!(self.frequency, Frequency::Daily | Frequency::Monthly)
+            && !self.by_month.is_empty()
+        {
+            dates.retain(|date| self.by_month.contains(&date.month));
+        }
+        dates
+    }
+
+    fn month_dates(&self, year: i32, month: u32, start: &Date) -> Vec<Date> {
+        let length = days_in_month(year, month);
+        let mut days: Vec<u32> = if !self.by_day.is_empty() {
+            let first_weekday = Date {
+                year,
+                month,
+                day: 1,
+            }
+            .weekday();
+            let mut days = vec![];
+            for (ordinal, weekday) in &self.by_day {
+                let first = 1 + (weekday + 7 - first_weekday) % 7;
+                let matching: Vec<u32> = (first..=length).step_by(7).collect();
+                days.extend(pick(&matching, *ordinal));
+            }
+            days
+        } else if !self.by_month_day.is_empty() {
+            self.by_month_day
+                .iter()
+                .filter_map(|day| match *day {
+                    d if d > 0 && d as u32 <= length => Some(d as u32),
+                    d if d < 0 && d.unsigned_abs() <= length => Some(length + 1 - d.unsigned_abs()),
+                    _ => None,
+                })
+                .collect()
+        } else if start.day <= length {
+            // Months without the day, like the 31st, are skipped
+            vec![start.day]
+        } else {
+            vec![]
+        };
+
+        days.sort();
+        days.dedup();
+        days.into_iter()
+            .map(|day| Date { year, month, day })
+            .collect()
+    }
+
+    /// Dates of a year that match BYDAY, counting weekdays from January 1st.
+    fn year_dates(&self, year: i32) -> Vec<Date> {
+        let first_day = Date {
+            year,
+            month: 1,
+            day: 1,
+        };
+        let length = if days_in_month(year, 2) == 29 {
+            366
+        } else {
+            365
+        };
+
+        // Days since January 1st
+        let mut days = vec![];
+        for (ordinal, weekday) in &self.by_day {
+            let first = (weekday + 7 - first_day.weekday()) % 7;
+            let matching: Vec<u32> = (first..length).step_by(7).collect();
+            days.extend(pick(&matching, *ordinal));
+        }
+
+        days.sort();
+        days.dedup();
+        days.into_iter()
+            .map(|day| Date::from_days(first_day.days() + day as i64))
+            .collect()
+    }
+
+    /// First day of period `index`, counted from the period of `start`.
+    fn period_start(&self, start: &Date, index: i64) -> Date {
+        let step = index * self.interval as i64;
+        match self.frequency {
+            Frequency::Daily => Date::from_days(start.days() + step),
+            Frequency::Weekly => Date::from_days(start.days() - start.weekday() as i64 + step * 7),
+            Frequency::Monthly => Date { day: 1, ..*start }.add_months(step),
+            Frequency::Yearly => Date {
+                year: start.year + step as i32,
+                month: 1,
+                day: 1,
+            },
+        }
+    }
+
+    /// Dates of all occurrences starting from `start`, up to and including
+    /// `last`. Without a COUNT, periods before `first` are skipped.
+    fn dates(&self, start: &Date, first: &Date, last: &Date, until: Option<&Date>) -> Vec<Date> {
+        let period_days = match self.frequency {
+            Frequency::Daily => 1,
+            Frequency::Weekly => 7,
+            // Shortest possible, so no period of interest gets skipped
+            Frequency::Monthly => 31,
+            Frequency::Yearly => 366,
+        } * self.interval as i64;
+        let first_index = if self.count.is_none() {
+            ((first.days() - start.days()) / period_days - 1).max(0)
+        } else {
+            0
+        };
+
+        let mut dates = vec![];
+        let mut seen = 0;
+        for index in first_index..first_index + MAX_OCCURRENCES as i64 {
+            let period = self.period_start(start, index);
+            if period > *last || until.is_some_and(|until| period > *until) {
+                break;
+            }
+
+            for date in self.period_dates(period, start) {
+                if date < *start || !date.is_valid() {
+                    continue;
+                }
+                if date > *last || until.is_some_and(|until| date > *until) {
+                    return dates;
+                }
+                seen += 1;
+                if self.count.is_some_and(|count| seen > count) {
+                    return dates;
+                }
+                dates.push(date);
+            }
+        }
+        dates
+    }
+}
+
+/// The matches a BYDAY ordinal picks: all of them for 0, counted from the
+/// end when negative.
+fn pick(matching: &[u32], ordinal: i32) -> Vec<u32> {
+    let index = match ordinal {
+        0 => return matching.to_vec(),
+        n if n > 0 => n as usize - 1,
+        n => match matching.len().checked_sub(n.unsigned_abs() as usize) {
+            Some(index) => index,
+            None => return vec![],
+        },
+    };
+    matching.get(index).copied().into_iter().collect()
+}
+
+fn parse_weekday(value: &str) -> Option<(i32, u32)> {
+    let value = value.trim();
+    let split = value.len().checked_sub(2)?;
+    let weekday = match value.get(split..)?.to_ascii_uppercase().as_str() {
+        "MO" => 0,
+        "TU" => 1,
+        "WE" => 2,
+        "TH" => 3,
+        "FR" => 4,
+        "SA" => 5,
+        "SU" => 6,
+        _ => return None,
+    };
+    let ordinal = match &value[..split] {
+        "" => 0,
+        ordinal => ordinal.trim_start_matches('+').parse().ok()?,
+    };
+    Some((ordinal, weekday))
+}
+
+/// "PT1H30M", "P1D", "P2W" in seconds.
+fn parse_duration(value: &str) -> Option<i64> {
+    let (sign, value) = match value.trim().strip_prefix('-') {
+        Some(rest) => (-1, rest),
+        None => (1, value.trim().trim_start_matches('+')),
+    };
+    let value = value.strip_prefix('P')?;
+
+    let mut seconds = 0;
+    let mut number = String::new();
+    for c in value.chars() {
+        match c {
+            '0'..='9' => number.push(c),
+            'T' => {}
+            unit => {
+                let n: i64 = number.parse().ok()?;
+                number.clear();
+                seconds += n * match unit {
+                    'W' => 7 * DAY,
+                    'D' => DAY,
+                    'H' => 3600,
+                    'M' => 60,
+                    'S' => 1,
+                    _ => return None,
+                };
+            }
+        }
+    }
+    Some(sign * seconds)
+}
+
+fn unescape(text: &str) -> String {
+    let mut result = String::with_capacity(text.len());
+    let mut chars = text.chars();
+    while let Some(c) = chars.next() {
+        if c == '\\' {
+            match chars.next() {
+                Some('n') | Some('N') => result.push('\n'),
+                Some(other) => result.push(other),
+                None => {}
+            }
+        } else {
+            result.push(c);
+        }
+    }
+    result
+}
+
+#[derive(Debug, Clone)]
+pub struct Event {
+    uid: Option<String>,
+    summary: String,
+    start: Stamp,
+    end: Option<Stamp>,
+    duration: Option<i64>,
+    rule: Option<Rule>,
+    exdates: Vec<Stamp>,
+    recurrence_id: Option<Stamp>,
+    cancelled: bool,
+}
+
+/// Parameters and values of every property of a component, by name.
+type Properties = HashMap<String, Vec<(HashMap<String, String>, String)>>;
+
+/// One occurrence of an event, in unix time.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Occurrence {
+    pub start: i64,
+    pub end: i64,
+    pub all_day: bool,
+    pub summary: String,
+}
+
+/// Reads the VEVENTs of an iCalendar file. Broken events are skipped.
+pub fn parse(text: &str) -> Vec<Event> {
+    // Long lines are folded with a leading space or tab
+    let mut lines: Vec<String> = vec![];
+    for line in text.lines() {
+        match line.strip_prefix([' ', '\t']) {
+            Some(rest) if !lines.is_empty() => lines.last_mut().unwrap().push_str(rest),
+            _ => lines.push(line.to_string()),
+        }
+    }
+
+    let mut events = vec![];
+    let mut current: Option<Properties> = None;
+    // Components nested in the event, like VALARM
+    let mut nested = 0;
+    for line in lines {
+        let Some((name, params, value)) = parse_line(&line) else {
+            continue;
+        };
+        match (name.as_str(), value.to_ascii_uppercase().as_str()) {
+            ("BEGIN", "VEVENT") => current = Some(HashMap::new()),
+            ("END", "VEVENT") => {
+                if let Some(event) = current
+                    .take()
+                    .and_then(|props| Event::from_properties(&props))
+                {
+                    events.push(event);
+                }
+                nested = 0;
+            }
+            ("BEGIN", _) if current.is_some() => nested += 1,
+            ("END", _) if current.is_some() => nested -= 1,
+            _ => {
+                if nested == 0 {
+                    if let Some(props) = current.as_mut() {
+                        props.entry(name).or_default().push((params, value));
+                    }
+                }
+            }
+        }
+    }
+
+    events
+}
+
+fn parse_line(line: &str) -> Option<(String, HashMap<String, String>, String)> {
+    // The value starts at the first colon outside a quoted parameter
+    let mut quoted = false;
+    let colon = line.char_indices().find_map(|(i, c)| match c {
+        '"' => {
+            quoted = !quoted;
+            None
+        }
+        ':' if !quoted => Some(i),
+        _ => None,
+    })?;
+
+    let (head, value) = (&line[..colon], &line[colon + 1..]);
+    let mut parts = head.split(';');
+    let name = parts.next()?.trim().to_ascii_uppercase();
+    let params = parts
+        .filter_map(|param| param.split_once('='))
+        .map(|(key, value)| {
+            (
+                key.to_ascii_uppercase(),
+                value.trim_matches('"').to_string(),
+            )
+        })
+        .collect();
+
+    Some((name, params, value.to_string()))
+}
+
+impl Event {
+    fn from_properties(props: &Properties) -> Option<Self> {
+        let first = |name: &str| props.get(name).and_then(|values| values.first());
+        let stamp =
+            |name: &str| first(name).and_then(|(params, value)| Stamp::parse(value, params));
+
+        let start = stamp("DTSTART")?;
+        let exdates = props
+            .get("EXDATE")
+            .into_iter()
+            .flatten()
+            .flat_map(|(params, value)| value.split(',').filter_map(|v| Stamp::parse(v, params)))
+            .collect();
+
+        Some(Event {
+            uid: first("UID").map(|(_, uid)| uid.clone()),
+            summary: first("SUMMARY")
+                .map(|(_, summary)| unescape(summary))
+                .unwrap_or_default(),
+            start,
+            end: stamp("DTEND"),
+            duration: first("DURATION").and_then(|(_, duration)| parse_duration(duration)),
+            rule: first("RRULE").and_then(|(_, rule)| Rule::parse(rule)),
+            exdates,
+            recurrence_id: stamp("RECURRENCE-ID"),
+            cancelled: first("STATUS")
+                .is_some_and(|(_, status)| status.eq_ignore_ascii_case("CANCELLED")),
+        })
+    }
+
+    fn all_day(&self) -> bool {
+        self.start.time.is_none()
+    }
+
+    /// Length of each occurrence: whole days for all-day events, seconds
+    /// otherwise.
+    fn length(&self, zones: &mut TimeZones) -> i64 {
+        match (&self.end, self.duration) {
+            (Some(end), _) if self.all_day() => (end.date.days() - self.start.date.days()) * DAY,
+            (Some(end), _) => match (end.to_unix(zones), self.start.to_unix(zones)) {
+                (Some(end), Some(start)) => end - start,
+                _ => 0,
+            },
+            (None, Some(duration)) => duration,
+            (None, None) if self.all_day() => DAY,
+            (None, None) => 0,
+        }
+        .max(0)
+    }
+
+    fn occurrence(&self, start: &Stamp, length: i64, zones: &mut TimeZones) -> Option<Occurrence> {
+        let start_unix = start.to_unix(zones)?;
+        let end = if self.all_day() {
+            // Whole days in local time, across DST changes
+            start
+                .with_date(Date::from_days(start.date.days() + length / DAY))
+                .to_unix(zones)?
+        } else {
+            start_unix + length
+        };
+
+        Some(Occurrence {
+            start: start_unix,
+            end,
+            all_day: self.all_day(),
+            summary: self.summary.clone(),
+        })
+    }
+
+    /// Occurrences overlapping `from..to`, leaving out the ones in `skip`
+    /// (start times of moved occurrences).
+    fn occurrences(
+        &self,
+        from: i64,
+        to: i64,
+        skip: &[i64],
+        zones: &mut TimeZones,
+    ) -> Vec<Occurrence> {
+        let length = self.length(zones);
+        let overlaps = |o: &Occurrence| {
+            o.start < to && (o.end > from || (o.end == o.start && o.start >= from))
+        };
+
+        let Some(rule) = &self.rule else {
+            return self
+                .occurrence(&self.start, length, zones)
+                .filter(overlaps)
+                .into_iter()
+                .collect();
+        };
+
+        // Whole days around the window, time zones are sorted out below
+        let first = Date::from_days(from.div_euclid(DAY) - length / DAY - 2);
+        let last = Date::from_days(to.div_euclid(DAY) + 2);
+        let until = rule.until.as_ref().map(|until| until.date);
+        let until_unix = rule.until.as_ref().and_then(|until| until.to_unix(zones));
+        let exdates: Vec<i64> = self
+            .exdates
+            .iter()
+            .filter_map(|d| d.to_unix(zones))
+            .collect();
+
+        rule.dates(&self.start.date, &first, &last, until.as_ref())
+            .into_iter()
+            .filter_map(|date| self.occurrence(&self.start.with_date(date), length, zones))
+            .filter(|o| until_unix.is_none_or(|until| self.all_day() || o.start <= until))
+            .filter(|o| !exdates.contains(&o.start) && !skip.contains(&o.start))
+            .filter(overlaps)
+            .collect()
+    }
+}
+
+/// Expands recurring events and applies moved or cancelled occurrences.
+pub fn occurrences(events: &[Event], from: i64, to: i64) -> Vec<Occurrence> {
+    let mut zones = TimeZones::default();
+
+    // RECURRENCE-ID events replace one occurrence of the event with their UID
+    let mut moved: HashMap<&str, Vec<i64>> = HashMap::new();
+    for event in events {
+        if let (Some(uid), Some(recurrence_id)) = (&event.uid, &event.recurrence_id) {
+            if let Some(start) = recurrence_id.to_unix(&mut zones) {
+                moved.entry(uid.as_str()).or_default().push(start);
+            }
+        }
+    }
+
+    let mut occurrences: Vec<Occurrence> = vec![];
+    for event in events {
+        let skip = match (&event.uid, &event.recurrence_id) {
+            (Some(uid), None) => moved.get(uid.as_str()).cloned().unwrap_or_default(),
+            _ => vec![],
+        };
+        if event.cancelled {
+            continue;
+        }
+        occurrences.extend(event.occurrences(from, to, &skip, &mut zones));
+    }
+
+    occurrences.sort_by_key(|o| (o.start, !o.all_day));
+    occurrences
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn date(year: i32, month: u32, day: u32) -> Date {
+        Date { year, month, day }
+    }
+
+    /// Dates of `rule` starting on `start`, between `first` and `last`.
+    fn dates(rule: &str, start: Date, first: Date, last: Date) -> Vec<Date> {
+        let rule = Rule::parse(rule).unwrap();
+        let until = rule.until.as_ref().map(|until| until.date);
+        rule.dates(&start, &first, &last, until.as_ref())
+    }
+
+    /// Unix time of a day in January 2024, UTC.
+    fn january(day: i64, hour: i64) -> i64 {
+        1_704_067_200 + (day - 1) * DAY + hour * 3600
+    }
+
+    #[test]
+    fn days_round_trip() {
+        assert_eq!(Date::from_days(0), date(1970, 1, 1));
+        assert_eq!(date(2000, 3, 1).days(), 11_017);
+        assert_eq!(date(1969, 12, 31).days(), -1);
+        for days in (-1_000_000..1_000_000).step_by(997) {
+            assert_eq!(Date::from_days(days).days(), days);
+        }
+    }
+
+    #[test]
+    fn weekdays_and_leap_years() {
+        // A Thursday
+        assert_eq!(date(1970, 1, 1).weekday(), 3);
+        assert_eq!(date(2024, 1, 1).weekday(), 0);
+        assert!(date(2024, 2, 29).is_valid());
+        assert!(date(2000, 2, 29).is_valid());
+        assert!(!date(1900, 2, 29).is_valid());
+        assert!(!date(2023, 2, 29).is_valid());
+        assert!(!date(2023, 4, 31).is_valid());
+    }
+
+    #[test]
+    fn weekly_by_day() {
+        assert_eq!(
+            dates(
+                "FREQ=WEEKLY;BYDAY=MO,WE;COUNT=4",
+                date(2024, 1, 1),
+                date(2024, 1, 1),
+                date(2024, 12, 31)
+            ),
+            [
+                date(2024, 1, 1),
+                date(2024, 1, 3),
+                date(2024, 1, 8),
+                date(2024, 1, 10)
+            ]
+        );
+    }
+
+    #[test]
+    fn monthly_last_friday() {
+        assert_eq!(
+            dates(
+                "FREQ=MONTHLY;BYDAY=-1FR;COUNT=3",
+                date(2024, 1, 26),
+                date(2024, 1, 1),
+                date(2024, 12, 31)
+            ),
+            [date(2024, 1, 26), date(2024, 2, 23), date(2024, 3, 29)]
+        );
+    }
+
+    #[test]
+    fn count_starts_at_the_first_occurrence() {
+        let found = dates(
+            "FREQ=DAILY;COUNT=5",
+            date(2024, 1, 1),
+            date(2024, 1, 10),
+            date(2024, 1, 31),
+        );
+        assert_eq!(found.len(), 5);
+        assert_eq!(found.last(), Some(&date(2024, 1, 5)));
+    }
+
+    #[test]
+    fn until_includes_its_day() {
+        assert_eq!(
+            dates(
+                "FREQ=DAILY;UNTIL=20240103",
+                date(2024, 1, 1),
+                date(2024, 1, 1),
+                date(2024, 1, 31)
+            ),
+            [date(2024, 1, 1), date(2024, 1, 2), date(2024, 1, 3)]
+        );
+    }
+
+    #[test]
+    fn intervals_skip_periods() {
+        assert_eq!(
+            dates(
+                "FREQ=WEEKLY;INTERVAL=2;BYDAY=TU",
+                date(2024, 1, 2),
+                date(2024, 1, 1),
+                date(2024, 2, 1)
+            ),
+            [date(2024, 1, 2), date(2024, 1, 16), date(2024, 1, 30)]
+        );
+        // Months without the 31st are left out
+        assert_eq!(
+            dates(
+                "FREQ=MONTHLY;INTERVAL=3",
+                date(2024, 1, 31),
+                date(2024, 1, 1),
+                date(2024, 12, 31)
+            ),
+            [date(2024, 1, 31), date(2024, 7, 31), date(2024, 10, 31)]
+        );
+    }
+
+    #[test]
+    fn periods_before_the_window_are_skipped() {
+        let found = dates(
+            "FREQ=DAILY",
+            date(2000, 1, 1),
+            date(2024, 6, 10),
+            date(2024, 6, 12),
+        );
+        assert!(found.contains(&date(2024, 6, 10)));
+        assert_eq!(found.last(), Some(&date(2024, 6, 12)));
+        assert!(found.len() <= 5);
+    }
+
+    #[test]
+    fn yearly_by_day() {
+        // The 20th Monday of the year
+        assert_eq!(
+            dates(
+                "FREQ=YEARLY;BYDAY=20MO;COUNT=2",
+                date(2024, 5, 13),
+                date(2024, 1, 1),
+                date(2026, 12, 31)
+            ),
+            [date(2024, 5, 13), date(2025, 5, 19)]
+        );
+        // Thanksgiving
+        assert_eq!(
+            dates(
+                "FREQ=YEARLY;BYMONTH=11;BYDAY=4TH;COUNT=2",
+                date(2024, 11, 28),
+                date(2024, 1, 1),
+                date(2026, 12, 31)
+            ),
+            [date(2024, 11, 28), date(2025, 11, 27)]
+        );
+    }
+
+    #[test]
+    fn invalid_rules() {
+        assert_eq!(Rule::parse("FREQ=SECONDLY"), None);
+        assert_eq!(Rule::parse("FREQ=DAILY;INTERVAL=0"), None);
+        assert_eq!(Rule::parse("COUNT=3"), None);
+    }
+
+    #[test]
+    fn parses_folded_lines_and_skips_alarms() {
+        let text = "BEGIN:VCALENDAR\r\n\
+                    BEGIN:VEVENT\r\n\
+                    UID:1\r\n\
+                    DTSTART:20240101T100000Z\r\n\
+                    DTEND:20240101T110000Z\r\n\
+                    SUMMARY:Long\r\n  \\, folded summary\r\n\
+                    BEGIN:VALARM\r\n\
+                    TRIGGER:-PT15M\r\n\
+                    SUMMARY:Alarm\r\n\
+                    END:VALARM\r\n\
+                    LOCATION:Home\r\n\
+                    END:VEVENT\r\n\
+                    END:VCALENDAR\r\n";
+        let events = parse(text);
+        assert_eq!(events.len(), 1);
+        assert_eq!(events[0].summary, "Long , folded summary");
+        assert_eq!(events[0].start.time, Some((10, 0, 0)));
+        assert_eq!(events[0].start.zone, Zone::Utc);
+    }
+
+    #[test]
+    fn exdates_and_moved_occurrences() {
+        let text = "BEGIN:VCALENDAR\n\
+                    BEGIN:VEVENT\n\
+                    UID:standup\n\
+                    DTSTART:20240101T090000Z\n\
+                    DURATION:PT15M\n\
+                    RRULE:FREQ=DAILY;COUNT=5\n\
+                    EXDATE:20240102T090000Z\n\
+                    SUMMARY:Standup\n\
+                    END:VEVENT\n\
+                    BEGIN:VEVENT\n\
+                    UID:standup\n\
+                    RECURRENCE-ID:20240103T090000Z\n\
+                    DTSTART:20240103T140000Z\n\
+                    DURATION:PT15M\n\
+                    SUMMARY:Moved standup\n\
+                    END:VEVENT\n\
+                    BEGIN:VEVENT\n\
+                    UID:standup\n\
+                    RECURRENCE-ID:20240105T090000Z\n\
+                    DTSTART:20240105T090000Z\n\
+                    STATUS:CANCELLED\n\
+                    END:VEVENT\n\
+                    END:VCALENDAR\n";
+        let found = occurrences(&parse(text), january(1, 0), january(11, 0));
+        let starts: Vec<(i64, &str)> = found
+            .iter()
+            .map(|o| (o.start, o.summary.as_str()))
+            .collect();
+        assert_eq!(
+            starts,
+            [
+                (january(1, 9), "Standup"),
+                (january(3, 14), "Moved standup"),
+                (january(4, 9), "Standup"),
+            ]
+        );
+        assert_eq!(found[0].end - found[0].start, 15 * 60);
+    }
+}
@@ -0,0 +1,171 @@
+use std::collections::HashMap;
+use std::path::Path;
+
+use gtk::gio::{self, BusNameOwnerFlags, BusType, DBusConnection, DBusNodeInfo};
+use gtk::glib::{self, ToVariant, Variant};
+use tokio::sync::broadcast::error::RecvError;
+use tokio::sync::mpsc::UnboundedSender;
+use tracing::{debug, warn};
+
+use crate::config::APP_ID;
+use crate::events::{self, FrameEvent};
+use crate::photo::provider::{album_names, allowed_path, Config};
+use crate::remote::RemoteCommand;
+use crate::spawn;
+use crate::state::current_state;
+
+const OBJECT_PATH: &str = "/com/mcostea/PiPhotoFrame";
+const INTERFACE: &str = "com.mcostea.PiPhotoFrame.Frame";
+const ERROR_FAILED: &str = "com.mcostea.PiPhotoFrame.Error.Failed";
+
+const INTROSPECTION: &str = r#"
+<node>
+  <interface name="com.mcostea.PiPhotoFrame.Frame">
+    <method name="Next"/>
+    <method name="Previous"/>
+    <method name="Pause"/>
+    <method name="Resume"/>
+    <method name="ShowFile">
+      <arg name="path" type="s" direction="in"/>
+    </method>
+    <method name="SetAlbum">
+      <arg name="album" type="s" direction="in"/>
+    </method>
+    <property name="CurrentPath" type="s" access="read"/>
+    <property name="Paused" type="b" access="read"/>
+    <signal name="SlideChanged">
+      <arg name="path" type="s"/>
+    </signal>
+  </interface>
+</node>
+"#;
+
+/// Exports the frame interface at [`OBJECT_PATH`] on `connection`. Method
+/// calls become `RemoteCommand`s for the window.
+pub fn register(
+    connection: &DBusConnection,
+    config: &Config,
+    commands: UnboundedSender<RemoteCommand>,
+) -> Result<(), glib::Error> {
+    let node = DBusNodeInfo::for_xml(INTROSPECTION)?;
+    let interface = node
+        .lookup_interface(INTERFACE)
+        .expect("the introspection data has the interface");
+    let albums = album_names(config);
+    let config = config.clone();
+
+    connection.register_object(
+        OBJECT_PATH,
+        &interface,
+        move |_, _, _, _, method, parameters, invocation| {
+            let command = match method {
+                "Next" => Ok(RemoteCommand::Next),
+                "Previous" => Ok(RemoteCommand::Previous),
+                "Pause" => Ok(RemoteCommand::SetPaused(true)),
+                "Resume" => Ok(RemoteCommand::SetPaused(false)),
+                "ShowFile" => match parameters.get::<(String,)>() {
+                    Some((path,)) => match allowed_path(&config, Path::new(&path)) {
+                        Some(path) if path.is_file() => Ok(RemoteCommand::Show(path)),
+                        Some(_) => Err(format!("{} is not a file", path)),
+                        None => Err(format!("{} is not in the photo paths", path)),
+                    },
+                    None => Err("ShowFile needs a path".to_string()),
+                },
+                "SetAlbum" => match parameters.get::<(String,)>() {
+                    Some((album,)) if album.is_empty() || albums.contains(&album) => {
+                        Ok(RemoteCommand::Album(Some(album).filter(|a| !a.is_empty())))
+                    }
+                    Some((album,)) => Err(format!("unknown album {}", album)),
+                    None => Err("SetAlbum needs an album".to_string()),
+                },
+                _ => Err(format!("unknown method {}", method)),
+            };
+
+            match command.and_then(|command| {
+                commands
+                    .send(command)
+                    .map_err(|_| "the frame is shutting down".to_string())
+            }) {
+                Ok(()) => invocation.return_value(None),
+                Err(e) => invocation.return_dbus_error(ERROR_FAILED, &e),
+            }
+        },
+        |_, _, _, _, property| {
+            let state = current_state();
+            match property {
+                "CurrentPath" => state.photo.unwrap_or_default().to_variant(),
+                _ => state.paused.to_variant(),
+            }
+        },
+        |_, _, _, _, _, _| false,
+    )?;
+
+    emit_changes(connection.clone());
+    Ok(())
+}
+
+/// Registers the interface on the system bus too, which needs the policy
+/// file from data/dbus to own the name.
+pub fn own_system_name(config: &Config, commands: UnboundedSender<RemoteCommand>) {
+    let config = config.clone();
+    gio::bus_own_name(
+        BusType::System,
+        APP_ID,
+        BusNameOwnerFlags::NONE,
+        move |connection, _| {
+            if let Err(e) = register(&connection, &config, commands.clone()) {
+                warn!(
+                    "Failed to export the D-Bus interface on the system bus, {}",
+                    e
+                );
+            }
+        },
+        |_, name| debug!("Acquired {} on the system bus", name),
+        |_, name| warn!("Could not own {} on the system bus", name),
+    );
+}
+
+/// Sends `SlideChanged` and property changes for the events on the frame.
+fn emit_changes(connection: DBusConnection) {
+    spawn!(async move {
+        let mut receiver = events::subscribe();
+        loop {
+            let event = match receiver.recv().await {
+                Ok(event) => event,
+                Err(RecvError::Lagged(_)) => continue,
+                Err(RecvError::Closed) => break,
+            };
+
+            let changed = match event {
+                FrameEvent::SlideChanged { photo, .. } => {
+                    let path = photo.unwrap_or_default();
+                    if let Err(e) = connection.emit_signal(
+                        None,
+                        OBJECT_PATH,
+                        INTERFACE,
+                        "SlideChanged",
+                        Some(&(path.as_str(),).to_variant()),
+                    ) {
+                        warn!("Failed to emit SlideChanged, {}", e);
+                    }
+                    ("CurrentPath", path.to_variant())
+                }
+                FrameEvent::Paused => ("Paused", true.to_variant()),
+                FrameEvent::Resumed => ("Paused", false.to_variant()),
+                _ => continue,
+            };
+
+            let properties: HashMap<String, Variant> =
+                HashMap::from([(changed.0.to_string(), changed.1)]);
+            if let Err(e) = connection.emit_signal(
+                None,
+                OBJECT_PATH,
+                "org.freedesktop.DBus.Properties",
+                "PropertiesChanged",
+                Some(&(INTERFACE, properties, Vec::<String>::new()).to_variant()),
+            ) {
+                warn!("Failed to emit PropertiesChanged, {}", e);
+            }
+        }
+    });
+}
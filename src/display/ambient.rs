@@ -0,0 +1,259 @@
+use std::fs;
+use std::path::{Path, PathBuf};
+use std::sync::Arc;
+use std::time::Duration;
+
+use gtk::glib;
+use serde::Deserialize;
+use tokio::sync::mpsc::UnboundedReceiver;
+use tracing::{debug, warn};
+
+use super::power::{DisplayPower, DisplayPowerError};
+use crate::photo::provider::Config;
+
+const IIO_DIR: &str = "/sys/bus/iio/devices";
+/// Brightness changes smaller than this are not written
+const MIN_BRIGHTNESS_STEP: f64 = 0.01;
+/// The screen comes back on once the room is this much brighter than the
+/// darkness threshold, so it doesn't flicker around it
+const WAKE_FACTOR: f64 = 1.5;
+
+#[derive(Deserialize, Debug, Clone, Copy, PartialEq)]
+#[serde(rename_all = "lowercase")]
+pub enum AmbientLightSource {
+    /// Linux IIO light sensor, e.g. a TSL2561 or BH1750
+    Iio,
+    /// Lux readings published on `ambient_light_mqtt_topic`
+    Mqtt,
+}
+
+/// Lux from MQTT, either a plain number or JSON like `{"illuminance_lux": 12}`.
+#[derive(Deserialize)]
+struct LuxMessage {
+    illuminance_lux: Option<f64>,
+    illuminance: Option<f64>,
+    lux: Option<f64>,
+}
+
+pub fn parse_lux(payload: &[u8]) -> Option<f64> {
+    let payload = String::from_utf8_lossy(payload);
+    if let Ok(lux) = payload.trim().parse() {
+        return Some(lux);
+    }
+
+    let message: LuxMessage = json5::from_str(&payload)
+        .map_err(|e| warn!("Invalid light level {}, {}", payload, e))
+        .ok()?;
+    message
+        .illuminance_lux
+        .or(message.illuminance)
+        .or(message.lux)
+}
+
+pub struct AmbientSettings {
+    source: AmbientLightSource,
+    sensor: Option<PathBuf>,
+    mqtt_topic: Option<String>,
+    /// (lux, brightness) points sorted by lux
+    curve: Vec<(f64, f64)>,
+    smoothing: f64,
+    off_lux: Option<f64>,
+    interval: Duration,
+}
+
+impl AmbientSettings {
+    /// Returns `None` when no light sensor is configured or found.
+    pub fn new(config: &Config) -> Option<Self> {
+        let source = config.ambient_light_source?;
+        let sensor = match source {
+            AmbientLightSource::Iio => {
+                let sensor = config
+                    .ambient_light_sensor
+                    .as_ref()
+                    .map(PathBuf::from)
+                    .or_else(find_iio_sensor);
+                if sensor.is_none() {
+                    warn!("No light sensor found in {}", IIO_DIR);
+                    return None;
+                }
+                sensor
+            }
+            AmbientLightSource::Mqtt => {
+                if !config.mqtt {
+                    warn!("The mqtt light source needs mqtt, not adjusting brightness");
+                    return None;
+                }
+                if config.ambient_light_mqtt_topic.is_none() {
+                    warn!("No ambient_light_mqtt_topic, not adjusting brightness");
+                    return None;
+                }
+                None
+            }
+        };
+
+        let mut curve: Vec<(f64, f64)> = config
+            .ambient_light_curve
+            .iter()
+            .map(|[lux, brightness]| (*lux, brightness.clamp(0.0, 1.0)))
+            .collect();
+        curve.sort_by(|a, b| a.0.total_cmp(&b.0));
+        if curve.is_empty() {
+            warn!("Empty ambient_light_curve, not adjusting brightness");
+            return None;
+        }
+
+        Some(AmbientSettings {
+            source,
+            sensor,
+            mqtt_topic: config.ambient_light_mqtt_topic.clone(),
+            curve,
+            smoothing: config.ambient_light_smoothing.clamp(0.01, 1.0),
+            off_lux: config.ambient_light_off_lux,
+            interval: Duration::from_secs(config.ambient_light_interval_seconds.max(1) as u64),
+        })
+    }
+
+    /// The topic to subscribe to for the MQTT source.
+    pub fn mqtt_topic(&self) -> Option<&str> {
+        match self.source {
+            AmbientLightSource::Mqtt => self.mqtt_topic.as_deref(),
+            AmbientLightSource::Iio => None,
+        }
+    }
+
+    /// Linear interpolation between the curve points.
+    fn brightness(&self, lux: f64) -> f64 {
+        let (first, last) = (self.curve[0], self.curve[self.curve.len() - 1]);
+        if lux <= first.0 {
+            return first.1;
+        }
+        if lux >= last.0 {
+            return last.1;
+        }
+
+        self.curve
+            .windows(2)
+            .find(|points| lux <= points[1].0)
+            .map(|points| {
+                let ((lux0, b0), (lux1, b1)) = (points[0], points[1]);
+                if lux1 <= lux0 {
+                    return b1;
+                }
+                b0 + (b1 - b0) * (lux - lux0) / (lux1 - lux0)
+            })
+            .unwrap_or(last.1)
+    }
+}
+
+/// The first IIO device with an illuminance channel.
+fn find_iio_sensor() -> Option<PathBuf> {
+    let mut devices: Vec<PathBuf> = fs::read_dir(IIO_DIR)
+        .ok()?
+        .filter_map(|entry| entry.ok())
+        .map(|entry| entry.path())
+        .collect();
+    devices.sort();
+
+    devices.into_iter().find_map(|device| {
+        ["in_illuminance_input", "in_illuminance_raw"]
+            .iter()
+            .map(|file| device.join(file))
+            .find(|path| path.is_file())
+    })
+}
+
+/// Reads lux from an IIO channel. Raw values are converted with the
+/// channel's `_scale` and `_offset` files when present.
+fn read_iio(path: &Path) -> Result<f64, String> {
+    let read = |path: &Path| -> Result<f64, String> {
+        fs::read_to_string(path)
+            .map_err(|e| e.to_string())?
+            .trim()
+            .parse::<f64>()
+            .map_err(|e| e.to_string())
+    };
+
+    let value = read(path)?;
+    let Some(raw) = path
+        .file_name()
+        .and_then(|name| name.to_str())
+        .and_then(|name| name.strip_suffix("_raw"))
+    else {
+        return Ok(value);
+    };
+
+    let offset = read(&path.with_file_name(format!("{}_offset", raw))).unwrap_or(0.0);
+    let scale = read(&path.with_file_name(format!("{}_scale", raw))).unwrap_or(1.0);
+    Ok((value + offset) * scale)
+}
+
+/// Follows the light level: sets the backlight brightness from the curve and
+/// sends `true` when the room goes dark, `false` when it lights up again.
+pub async fn run(
+    settings: AmbientSettings,
+    power: Arc<dyn DisplayPower>,
+    mut lux_receiver: UnboundedReceiver<f64>,
+    dark_sender: glib::Sender<bool>,
+) {
+    let mut smoothed: Option<f64> = None;
+    let mut brightness: Option<f64> = None;
+    let mut adjust_brightness = true;
+    let mut dark = false;
+
+    loop {
+        let lux = match (&settings.source, &settings.sensor) {
+            (AmbientLightSource::Iio, Some(sensor)) => {
+                tokio::time::sleep(settings.interval).await;
+                match read_iio(sensor) {
+                    Ok(lux) => lux,
+                    Err(e) => {
+                        warn!("Failed to read light sensor {:?}, {}", sensor, e);
+                        continue;
+                    }
+                }
+            }
+            _ => match lux_receiver.recv().await {
+                Some(lux) => lux,
+                None => break,
+            },
+        };
+
+        let lux = match smoothed {
+            Some(previous) => previous + settings.smoothing * (lux - previous),
+            None => lux,
+        };
+        smoothed = Some(lux);
+
+        let target = settings.brightness(lux);
+        if adjust_brightness
+            && brightness.is_none_or(|b: f64| (b - target).abs() >= MIN_BRIGHTNESS_STEP)
+        {
+            debug!(lux, brightness = target, "Adjusting brightness");
+            brightness = Some(target);
+            let power = power.clone();
+            let result = tokio::task::spawn_blocking(move || power.set_brightness(target)).await;
+            match result {
+                Ok(Err(DisplayPowerError::Unsupported)) => {
+                    warn!("The display backend cannot change the brightness");
+                    adjust_brightness = false;
+                }
+                Ok(Err(e)) => warn!("Failed to set brightness, {}", e),
+                _ => {}
+            }
+        }
+
+        if let Some(off_lux) = settings.off_lux {
+            let now_dark = if dark {
+                lux < (off_lux * WAKE_FACTOR).max(off_lux + 1.0)
+            } else {
+                lux < off_lux
+            };
+            if now_dark != dark {
+                dark = now_dark;
+                if dark_sender.send(dark).is_err() {
+                    break;
+                }
+            }
+        }
+    }
+}
@@ -0,0 +1,4 @@
+pub mod ambient;
+pub mod power;
+pub mod presence;
+pub mod schedule;
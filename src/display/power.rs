@@ -0,0 +1,227 @@
+use std::fmt::{self, Debug};
+use std::fs;
+use std::io::{self, Write};
+use std::path::{Path, PathBuf};
+use std::process::{Command, Stdio};
+use std::sync::Arc;
+
+use serde::Deserialize;
+use tracing::warn;
+
+use crate::photo::provider::Config;
+
+const BACKLIGHT_DIR: &str = "/sys/class/backlight";
+/// `bl_power` values, from the fbdev blanking levels
+const BL_POWER_ON: &str = "0";
+const BL_POWER_OFF: &str = "1";
+
+#[derive(Deserialize, Default, Debug, Clone, Copy, PartialEq)]
+#[serde(rename_all = "lowercase")]
+pub enum DisplayPowerBackend {
+    /// `bl_power` and `brightness` of a backlight in /sys/class/backlight
+    #[default]
+    Sysfs,
+    /// X11 DPMS through `xset`
+    Dpms,
+    /// wlroots compositors through `wlopm` (wlr-output-power-management)
+    Wlr,
+    /// HDMI TVs through `cec-client`
+    Cec,
+    /// Leave the display alone
+    None,
+}
+
+#[derive(Debug)]
+pub enum DisplayPowerError {
+    /// No backlight device in /sys/class/backlight
+    NoBacklight,
+    Io(PathBuf, io::Error),
+    /// The command could not be started, e.g. because it is not installed
+    Spawn(String, io::Error),
+    /// The command exited with an error, with its stderr
+    Command(String, String),
+    /// The backend cannot change the brightness
+    Unsupported,
+}
+
+impl fmt::Display for DisplayPowerError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            DisplayPowerError::NoBacklight => write!(f, "no backlight found in {}", BACKLIGHT_DIR),
+            DisplayPowerError::Io(path, e) => write!(f, "{}: {}", path.display(), e),
+            DisplayPowerError::Spawn(program, e) => write!(f, "failed to run {}: {}", program, e),
+            DisplayPowerError::Command(program, stderr) => {
+                write!(f, "{} failed: {}", program, stderr)
+            }
+            DisplayPowerError::Unsupported => write!(f, "not supported by this display backend"),
+        }
+    }
+}
+
+impl std::error::Error for DisplayPowerError {}
+
+/// Turns the screen on and off. Calls can block for a few seconds, e.g.
+/// while `cec-client` talks to the TV, so keep them off the main thread.
+pub trait DisplayPower: Send + Sync + Debug {
+    fn set_power(&self, on: bool) -> Result<(), DisplayPowerError>;
+
+    /// `level` goes from 0.0 to 1.0.
+    fn set_brightness(&self, _level: f64) -> Result<(), DisplayPowerError> {
+        Err(DisplayPowerError::Unsupported)
+    }
+}
+
+/// Creates the backend chosen in the config. Falls back to doing nothing
+/// when the backend cannot be used.
+pub fn create_backend(config: &Config) -> Arc<dyn DisplayPower> {
+    match config.display_power {
+        DisplayPowerBackend::Sysfs => match Sysfs::new(config.display_backlight.as_deref()) {
+            Ok(sysfs) => Arc::new(sysfs),
+            Err(e) => {
+                warn!("Display power control is disabled, {}", e);
+                Arc::new(Noop)
+            }
+        },
+        DisplayPowerBackend::Dpms => Arc::new(Dpms),
+        DisplayPowerBackend::Wlr => Arc::new(Wlr {
+            output: config
+                .display_output
+                .clone()
+                .unwrap_or_else(|| "*".to_string()),
+        }),
+        DisplayPowerBackend::Cec => Arc::new(Cec),
+        DisplayPowerBackend::None => Arc::new(Noop),
+    }
+}
+
+/// Writes the backlight files directly. Needs write access to them, e.g.
+/// through a udev rule, instead of sudo.
+#[derive(Debug)]
+struct Sysfs {
+    device: PathBuf,
+}
+
+impl Sysfs {
+    /// Uses the named backlight, or the first one found.
+    fn new(name: Option<&str>) -> Result<Self, DisplayPowerError> {
+        let dir = Path::new(BACKLIGHT_DIR);
+        let device = match name {
+            Some(name) => dir.join(name),
+            None => {
+                let entries =
+                    fs::read_dir(dir).map_err(|e| DisplayPowerError::Io(dir.to_path_buf(), e))?;
+                let mut devices: Vec<PathBuf> = entries
+                    .filter_map(|entry| entry.ok())
+                    .map(|entry| entry.path())
+                    .collect();
+                devices.sort();
+                devices
+                    .into_iter()
+                    .next()
+                    .ok_or(DisplayPowerError::NoBacklight)?
+            }
+        };
+
+        if !device.exists() {
+            return Err(DisplayPowerError::NoBacklight);
+        }
+        Ok(Sysfs { device })
+    }
+
+    fn write(&self, file: &str, value: &str) -> Result<(), DisplayPowerError> {
+        let path = self.device.join(file);
+        fs::write(&path, value).map_err(|e| DisplayPowerError::Io(path, e))
+    }
+}
+
+impl DisplayPower for Sysfs {
+    fn set_power(&self, on: bool) -> Result<(), DisplayPowerError> {
+        self.write("bl_power", if on { BL_POWER_ON } else { BL_POWER_OFF })
+    }
+
+    fn set_brightness(&self, level: f64) -> Result<(), DisplayPowerError> {
+        let path = self.device.join("max_brightness");
+        let max: u32 = fs::read_to_string(&path)
+            .map_err(|e| DisplayPowerError::Io(path.clone(), e))?
+            .trim()
+            .parse()
+            .map_err(|_| {
+                DisplayPowerError::Io(path, io::Error::from(io::ErrorKind::InvalidData))
+            })?;
+
+        let value = (level.clamp(0.0, 1.0) * max as f64).round() as u32;
+        self.write("brightness", &value.to_string())
+    }
+}
+
+#[derive(Debug)]
+struct Dpms;
+
+impl DisplayPower for Dpms {
+    fn set_power(&self, on: bool) -> Result<(), DisplayPowerError> {
+        run(
+            "xset",
+            &["dpms", "force", if on { "on" } else { "off" }],
+            None,
+        )
+    }
+}
+
+#[derive(Debug)]
+struct Wlr {
+    /// Output name, or "*" for all of them
+    output: String,
+}
+
+impl DisplayPower for Wlr {
+    fn set_power(&self, on: bool) -> Result<(), DisplayPowerError> {
+        let action = if on { "--on" } else { "--off" };
+        run("wlopm", &[action, &self.output], None)
+    }
+}
+
+/// Talks to the TV, logical address 0, through the first CEC adapter.
+#[derive(Debug)]
+struct Cec;
+
+impl DisplayPower for Cec {
+    fn set_power(&self, on: bool) -> Result<(), DisplayPowerError> {
+        let command = if on { "on 0" } else { "standby 0" };
+        run("cec-client", &["-s", "-d", "1"], Some(command))
+    }
+}
+
+#[derive(Debug)]
+struct Noop;
+
+impl DisplayPower for Noop {
+    fn set_power(&self, _on: bool) -> Result<(), DisplayPowerError> {
+        Ok(())
+    }
+}
+
+fn run(program: &str, args: &[&str], input: Option<&str>) -> Result<(), DisplayPowerError> {
+    let spawn_error = |e| DisplayPowerError::Spawn(program.to_string(), e);
+    let mut child = Command::new(program)
+        .args(args)
+        .stdin(if input.is_some() {
+            Stdio::piped()
+        } else {
+            Stdio::null()
+        })
+        .stdout(Stdio::null())
+        .stderr(Stdio::piped())
+        .spawn()
+        .map_err(spawn_error)?;
+
+    if let (Some(input), Some(mut stdin)) = (input, child.stdin.take()) {
+        stdin.write_all(input.as_bytes()).map_err(spawn_error)?;
+    }
+
+    let output = child.wait_with_output().map_err(spawn_error)?;
+    if !output.status.success() {
+        let stderr = String::from_utf8_lossy(&output.stderr).trim().to_string();
+        return Err(DisplayPowerError::Command(program.to_string(), stderr));
+    }
+    Ok(())
+}
@@ -0,0 +1,319 @@
+use std::fs::{self, File};
+use std::path::{Path, PathBuf};
+use std::time::{Duration, Instant};
+
+use gtk::glib;
+use serde::Deserialize;
+use tokio::sync::mpsc::UnboundedReceiver;
+use tracing::{debug, warn};
+
+use crate::photo::provider::Config;
+
+const GPIO_DIR: &str = "/sys/class/gpio";
+/// Short enough that the screen wakes as soon as someone walks in
+const GPIO_POLL_INTERVAL: Duration = Duration::from_millis(200);
+const MQTT_CHECK_INTERVAL: Duration = Duration::from_secs(1);
+
+#[derive(Deserialize, Debug, Clone, Copy, PartialEq)]
+#[serde(rename_all = "lowercase")]
+pub enum PresenceSource {
+    /// PIR motion sensor on a GPIO line
+    Gpio,
+    /// Occupancy published on `presence_mqtt_topic`
+    Mqtt,
+}
+
+/// Occupancy from MQTT, either a plain value like "ON", "1" or "true", or
+/// JSON like `{"occupancy": true}`.
+#[derive(Deserialize)]
+struct OccupancyMessage {
+    occupancy: Option<bool>,
+    presence: Option<bool>,
+    motion: Option<bool>,
+}
+
+pub fn parse_occupancy(payload: &[u8]) -> Option<bool> {
+    let payload = String::from_utf8_lossy(payload);
+    match payload.trim().to_lowercase().as_str() {
+        "1" | "on" | "true" | "occupied" | "detected" => return Some(true),
+        "0" | "off" | "false" | "clear" | "unoccupied" => return Some(false),
+        _ => {}
+    }
+
+    let message: OccupancyMessage = json5::from_str(&payload)
+        .map_err(|e| warn!("Invalid occupancy {}, {}", payload, e))
+        .ok()?;
+    message.occupancy.or(message.presence).or(message.motion)
+}
+
+/// A GPIO line, read through a GPIO character device or the older sysfs
+/// interface.
+struct GpioLine {
+    input: GpioInput,
+    active_low: bool,
+}
+
+enum GpioInput {
+    /// The `value` file in /sys/class/gpio
+    Sysfs(PathBuf),
+    /// A line request from `/dev/gpiochipN`
+    Chip(File),
+}
+
+impl GpioLine {
+    /// Requests the line as an input from `chip`, or without a chip exports
+    /// it in sysfs if it isn't already.
+    fn open(chip: Option<&str>, line: u32, active_low: bool) -> Result<Self, String> {
+        let input = match chip {
+            Some(chip) => GpioInput::Chip(
+                chardev::request_input(Path::new(chip), line)
+                    .map_err(|e| format!("failed to request line {} of {}, {}", line, chip, e))?,
+            ),
+            None => GpioInput::Sysfs(GpioLine::export(line)?),
+        };
+
+        Ok(GpioLine { input, active_low })
+    }
+
+    fn export(line: u32) -> Result<PathBuf, String> {
+        let dir = PathBuf::from(GPIO_DIR).join(format!("gpio{}", line));
+        if !dir.exists() {
+            fs::write(PathBuf::from(GPIO_DIR).join("export"), line.to_string())
+                .map_err(|e| format!("failed to export GPIO {}, {}", line, e))?;
+        }
+        // Usually already an input, and not writable without extra permissions
+        if let Err(e) = fs::write(dir.join("direction"), "in") {
+            debug!("Could not set GPIO {} direction, {}", line, e);
+        }
+
+        Ok(dir.join("value"))
+    }
+
+    fn is_active(&self) -> Result<bool, String> {
+        let high = match &self.input {
+            GpioInput::Sysfs(value) => {
+                fs::read_to_string(value).map_err(|e| e.to_string())?.trim() == "1"
+            }
+            GpioInput::Chip(request) => chardev::read_value(request).map_err(|e| e.to_string())?,
+        };
+        Ok(high != self.active_low)
+    }
+}
+
+/// The GPIO character device interface (v2) from `linux/gpio.h`.
+mod chardev {
+    use std::fs::File;
+    use std::io::Error;
+    use std::os::fd::{AsRawFd, FromRawFd};
+    use std::path::Path;
+
+    const LINES_MAX: usize = 64;
+    const MAX_NAME_SIZE: usize = 32;
+    const LINE_NUM_ATTRS_MAX: usize = 10;
+    const LINE_FLAG_INPUT: u64 = 1 << 2;
+    /// `_IOWR(0xB4, 0x07, struct gpio_v2_line_request)`
+    const GET_LINE_IOCTL: u32 = 0xC250_B407;
+    /// `_IOWR(0xB4, 0x0E, struct gpio_v2_line_values)`
+    const LINE_GET_VALUES_IOCTL: u32 = 0xC010_B40E;
+
+    #[repr(C)]
+    struct LineAttribute {
+        id: u32,
+        padding: u32,
+        value: u64,
+    }
+
+    #[repr(C)]
+    struct LineConfigAttribute {
+        attr: LineAttribute,
+        mask: u64,
+    }
+
+    #[repr(C)]
+    struct LineConfig {
+        flags: u64,
+        num_attrs: u32,
+        padding: [u32; 5],
+        attrs: [LineConfigAttribute; LINE_NUM_ATTRS_MAX],
+    }
+
+    #[repr(C)]
+    struct LineRequest {
+        offsets: [u32; LINES_MAX],
+        consumer: [u8; MAX_NAME_SIZE],
+        config: LineConfig,
+        num_lines: u32,
+        event_buffer_size: u32,
+        padding: [u32; 5],
+        fd: i32,
+    }
+
+    #[repr(C)]
+    struct LineValues {
+        bits: u64,
+        mask: u64,
+    }
+
+    // The ioctl numbers encode these sizes
+    const _: () = assert!(std::mem::size_of::<LineRequest>() == 592);
+    const _: () = assert!(std::mem::size_of::<LineValues>() == 16);
+
+    /// Requests `line` of the chip as an input. The returned file holds the
+    /// line until it is dropped.
+    pub fn request_input(chip: &Path, line: u32) -> Result<File, Error> {
+        let chip = File::open(chip)?;
+
+        // SAFETY: all zeros is a valid value for these plain C structs
+        let mut request: LineRequest = unsafe { std::mem::zeroed() };
+        request.offsets[0] = line;
+        request.num_lines = 1;
+        request.config.flags = LINE_FLAG_INPUT;
+        let consumer = b"photo-frame";
+        request.consumer[..consumer.len()].copy_from_slice(consumer);
+
+        // SAFETY: the request matches struct gpio_v2_line_request and lives
+        // for the whole call
+        let result = unsafe {
+            libc::ioctl(
+                chip.as_raw_fd(),
+                GET_LINE_IOCTL as _,
+                &mut request as *mut LineRequest,
+            )
+        };
+        if result < 0 {
+            return Err(Error::last_os_error());
+        }
+        if request.fd < 0 {
+            return Err(Error::other("no line file descriptor"));
+        }
+
+        // SAFETY: the kernel just opened this descriptor for us
+        Ok(unsafe { File::from_raw_fd(request.fd) })
+    }
+
+    /// Whether the requested line is high.
+    pub fn read_value(request: &File) -> Result<bool, Error> {
+        let mut values = LineValues { bits: 0, mask: 1 };
+        // SAFETY: values matches struct gpio_v2_line_values and lives for the
+        // whole call
+        let result = unsafe {
+            libc::ioctl(
+                request.as_raw_fd(),
+                LINE_GET_VALUES_IOCTL as _,
+                &mut values as *mut LineValues,
+            )
+        };
+        if result < 0 {
+            return Err(Error::last_os_error());
+        }
+        Ok(values.bits & 1 != 0)
+    }
+}
+
+pub struct PresenceSettings {
+    source: PresenceSource,
+    gpio_chip: Option<String>,
+    gpio_line: Option<u32>,
+    gpio_active_low: bool,
+    mqtt_topic: Option<String>,
+    timeout: Duration,
+}
+
+impl PresenceSettings {
+    /// Returns `None` when no presence sensor is configured.
+    pub fn new(config: &Config) -> Option<Self> {
+        let source = config.presence_source?;
+        if source == PresenceSource::Gpio && config.presence_gpio.is_none() {
+            warn!("presence_gpio is needed for the gpio presence source");
+            return None;
+        }
+
+        Some(PresenceSettings {
+            source,
+            gpio_chip: config.presence_gpio_chip.clone(),
+            gpio_line: config.presence_gpio,
+            gpio_active_low: config.presence_gpio_active_low,
+            mqtt_topic: config.presence_mqtt_topic.clone(),
+            timeout: Duration::from_secs(config.presence_timeout_minutes as u64 * 60),
+        })
+    }
+
+    /// The topic to subscribe to for the MQTT source.
+    pub fn mqtt_topic(&self) -> Option<&str> {
+        match self.source {
+            PresenceSource::Mqtt => self.mqtt_topic.as_deref(),
+            PresenceSource::Gpio => None,
+        }
+    }
+}
+
+/// Watches the sensor and sends `false` once nobody was seen for the
+/// timeout, `true` as soon as there is motion again.
+pub async fn run(
+    settings: PresenceSettings,
+    mut occupancy_receiver: UnboundedReceiver<bool>,
+    sender: glib::Sender<bool>,
+) {
+    let gpio = match (settings.source, settings.gpio_line) {
+        (PresenceSource::Gpio, Some(line)) => {
+            match GpioLine::open(
+                settings.gpio_chip.as_deref(),
+                line,
+                settings.gpio_active_low,
+            ) {
+                Ok(gpio) => Some(gpio),
+                Err(e) => {
+                    warn!("Presence detection is disabled, {}", e);
+                    return;
+                }
+            }
+        }
+        _ => None,
+    };
+
+    let mut ticker = tokio::time::interval(if gpio.is_some() {
+        GPIO_POLL_INTERVAL
+    } else {
+        MQTT_CHECK_INTERVAL
+    });
+    let mut motion = false;
+    let mut last_seen = Instant::now();
+    let mut present = true;
+    let mut read_failed = false;
+
+    loop {
+        tokio::select! {
+            _ = ticker.tick() => {
+                if let Some(gpio) = &gpio {
+                    match gpio.is_active() {
+                        Ok(active) => {
+                            motion = active;
+                            read_failed = false;
+                        }
+                        Err(e) if !read_failed => {
+                            warn!("Failed to read motion sensor, {}", e);
+                            read_failed = true;
+                        }
+                        Err(_) => {}
+                    }
+                }
+            }
+            occupancy = occupancy_receiver.recv(), if gpio.is_none() => match occupancy {
+                Some(occupancy) => motion = occupancy,
+                None => break,
+            },
+        }
+
+        if motion {
+            last_seen = Instant::now();
+        }
+        let now_present = motion || last_seen.elapsed() < settings.timeout;
+        if now_present != present {
+            debug!(present = now_present, "Presence changed");
+            present = now_present;
+            if sender.send(present).is_err() {
+                break;
+            }
+        }
+    }
+}
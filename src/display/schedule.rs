@@ -0,0 +1,228 @@
+use std::cmp::Ordering;
+
+use gtk::glib::DateTime;
+use serde::Deserialize;
+use tracing::warn;
+
+use crate::gui::night::parse_time;
+use crate::photo::provider::Config;
+
+/// The `display_schedule` section of the config.
+#[derive(Deserialize, Debug, Clone, Default)]
+pub struct DisplayScheduleConfig {
+    /// Monday to Friday, always on when missing
+    #[serde(default)]
+    pub weekdays: Option<OnOffTimes>,
+    /// Saturday and Sunday, always on when missing
+    #[serde(default)]
+    pub weekends: Option<OnOffTimes>,
+    /// "YYYY-MM-DD" for a single day or "MM-DD" for every year
+    #[serde(default)]
+    pub holidays: Vec<String>,
+    /// Keep the display off all day on holidays instead of following the
+    /// weekend times
+    #[serde(default)]
+    pub holidays_off: bool,
+}
+
+#[derive(Deserialize, Debug, Clone)]
+pub struct OnOffTimes {
+    pub on: String,
+    pub off: String,
+}
+
+/// Minutes since midnight. `off` can be earlier than `on` when the display
+/// stays on past midnight.
+#[derive(Debug, Clone, Copy)]
+struct Period {
+    on: u32,
+    off: u32,
+}
+
+impl Period {
+    fn new(times: &OnOffTimes) -> Option<Self> {
+        match (parse_time(&times.on), parse_time(&times.off)) {
+            (Some(on), Some(off)) => Some(Period { on, off }),
+            _ => {
+                warn!("Invalid display schedule {} - {}", times.on, times.off);
+                None
+            }
+        }
+    }
+
+    fn contains(&self, minute: u32) -> bool {
+        match self.on.cmp(&self.off) {
+            Ordering::Less => self.on <= minute && minute < self.off,
+            Ordering::Greater => minute >= self.on || minute < self.off,
+            Ordering::Equal => true,
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+enum Holiday {
+    Once(i32, i32, i32),
+    Yearly(i32, i32),
+}
+
+impl Holiday {
+    fn parse(text: &str) -> Option<Self> {
+        let parts: Vec<i32> = text
+            .trim()
+            .split('-')
+            .map(|part| part.parse().ok())
+            .collect::<Option<_>>()?;
+        let (year, month, day) = match parts[..] {
+            [year, month, day] => (Some(year), month, day),
+            [month, day] => (None, month, day),
+            _ => return None,
+        };
+        if !(1..=days_in_month(year, month)).contains(&day) {
+            return None;
+        }
+
+        Some(match year {
+            Some(year) => Holiday::Once(year, month, day),
+            None => Holiday::Yearly(month, day),
+        })
+    }
+
+    fn matches(&self, year: i32, month: i32, day: i32) -> bool {
+        match *self {
+            Holiday::Once(y, m, d) => (y, m, d) == (year, month, day),
+            Holiday::Yearly(m, d) => (m, d) == (month, day),
+        }
+    }
+}
+
+/// Days in the month, 29 in February without a year. 0 for an invalid month.
+fn days_in_month(year: Option<i32>, month: i32) -> i32 {
+    match month {
+        1 | 3 | 5 | 7 | 8 | 10 | 12 => 31,
+        4 | 6 | 9 | 11 => 30,
+        2 => match year {
+            Some(year) if !(year % 4 == 0 && (year % 100 != 0 || year % 400 == 0)) => 28,
+            _ => 29,
+        },
+        _ => 0,
+    }
+}
+
+/// When the display should be powered on, by day of the week.
+#[derive(Debug, Clone)]
+pub struct DisplaySchedule {
+    weekdays: Option<Period>,
+    weekends: Option<Period>,
+    holidays: Vec<Holiday>,
+    holidays_off: bool,
+}
+
+impl DisplaySchedule {
+    /// Returns `None` when no schedule is configured.
+    pub fn new(config: &Config) -> Option<Self> {
+        let schedule = config.display_schedule.as_ref()?;
+        let holidays = schedule
+            .holidays
+            .iter()
+            .filter_map(|text| {
+                let holiday = Holiday::parse(text);
+                if holiday.is_none() {
+                    warn!("Invalid holiday {}", text);
+                }
+                holiday
+            })
+            .collect();
+
+        Some(DisplaySchedule {
+            weekdays: schedule.weekdays.as_ref().and_then(Period::new),
+            weekends: schedule.weekends.as_ref().and_then(Period::new),
+            holidays,
+            holidays_off: schedule.holidays_off,
+        })
+    }
+
+    pub fn is_on(&self, now: &DateTime) -> bool {
+        let (year, month, day) = now.ymd();
+        let holiday = self
+            .holidays
+            .iter()
+            .any(|holiday| holiday.matches(year, month, day));
+        if holiday && self.holidays_off {
+            return false;
+        }
+
+        let period = if holiday || now.day_of_week() >= 6 {
+            self.weekends
+        } else {
+            self.weekdays
+        };
+        let minute = (now.hour() * 60 + now.minute()) as u32;
+        period.is_none_or(|period| period.contains(minute))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn period_during_the_day() {
+        let period = Period { on: 420, off: 1320 };
+        assert!(!period.contains(419));
+        assert!(period.contains(420));
+        assert!(period.contains(1319));
+        assert!(!period.contains(1320));
+    }
+
+    #[test]
+    fn period_overnight() {
+        let period = Period { on: 1320, off: 120 };
+        assert!(period.contains(1320));
+        assert!(period.contains(0));
+        assert!(period.contains(119));
+        assert!(!period.contains(120));
+        assert!(!period.contains(720));
+    }
+
+    #[test]
+    fn period_with_equal_times_is_always_on() {
+        let period = Period { on: 600, off: 600 };
+        assert!(period.contains(0));
+        assert!(period.contains(600));
+        assert!(period.contains(1439));
+    }
+
+    #[test]
+    fn parses_holidays() {
+        assert_eq!(
+            Holiday::parse("2024-12-25"),
+            Some(Holiday::Once(2024, 12, 25))
+        );
+        assert_eq!(Holiday::parse(" 01-01 "), Some(Holiday::Yearly(1, 1)));
+        assert_eq!(Holiday::parse("02-29"), Some(Holiday::Yearly(2, 29)));
+        assert_eq!(
+            Holiday::parse("2024-02-29"),
+            Some(Holiday::Once(2024, 2, 29))
+        );
+    }
+
+    #[test]
+    fn rejects_impossible_holidays() {
+        assert_eq!(Holiday::parse("13-45"), None);
+        assert_eq!(Holiday::parse("00-10"), None);
+        assert_eq!(Holiday::parse("04-31"), None);
+        assert_eq!(Holiday::parse("12-00"), None);
+        assert_eq!(Holiday::parse("2023-02-29"), None);
+        assert_eq!(Holiday::parse("1900-02-29"), None);
+        assert_eq!(Holiday::parse("christmas"), None);
+        assert_eq!(Holiday::parse("2024-12-25-1"), None);
+    }
+
+    #[test]
+    fn holidays_match_their_day() {
+        assert!(Holiday::Yearly(12, 25).matches(2030, 12, 25));
+        assert!(!Holiday::Yearly(12, 25).matches(2030, 12, 26));
+        assert!(Holiday::Once(2024, 5, 1).matches(2024, 5, 1));
+        assert!(!Holiday::Once(2024, 5, 1).matches(2025, 5, 1));
+    }
+}
@@ -0,0 +1,61 @@
+use once_cell::sync::Lazy;
+use serde::Serialize;
+use tokio::sync::broadcast;
+
+/// Slow subscribers miss events once this many are queued
+const CAPACITY: usize = 64;
+
+/// Things happening on the frame, for live clients. Unlike the state, every
+/// event is sent even when nothing changed, e.g. the same photo shown again.
+#[derive(Serialize, Debug, Clone)]
+#[serde(tag = "type", rename_all = "snake_case")]
+pub enum FrameEvent {
+    /// With what the overlay shows for it
+    SlideChanged {
+        photo: Option<String>,
+        date: Option<String>,
+        location: Option<String>,
+        caption: Option<String>,
+        camera: Option<String>,
+    },
+    Paused,
+    Resumed,
+    Display {
+        on: bool,
+    },
+    Error {
+        message: String,
+    },
+    ConfigReloaded,
+}
+
+impl FrameEvent {
+    /// Name of the event, the same as its `type`.
+    pub fn name(&self) -> &'static str {
+        match self {
+            FrameEvent::SlideChanged { .. } => "slide_changed",
+            FrameEvent::Paused => "paused",
+            FrameEvent::Resumed => "resumed",
+            FrameEvent::Display { .. } => "display",
+            FrameEvent::Error { .. } => "error",
+            FrameEvent::ConfigReloaded => "config_reloaded",
+        }
+    }
+}
+
+static EVENTS: Lazy<broadcast::Sender<FrameEvent>> = Lazy::new(|| broadcast::channel(CAPACITY).0);
+
+pub fn emit(event: FrameEvent) {
+    // Nobody listening is fine
+    let _ = EVENTS.send(event);
+}
+
+pub fn emit_error(message: impl Into<String>) {
+    emit(FrameEvent::Error {
+        message: message.into(),
+    });
+}
+
+pub fn subscribe() -> broadcast::Receiver<FrameEvent> {
+    EVENTS.subscribe()
+}
@@ -0,0 +1,93 @@
+use std::time::Duration;
+
+use gtk::glib::{DateTime, TimeZone};
+use tracing::warn;
+
+use crate::photo::provider::Config;
+
+/// Wake up a little after the boundary so the new minute is already visible
+/// to `DateTime::now_local`.
+const TICK_MARGIN: Duration = Duration::from_millis(20);
+
+/// Formats for the clock overlay, built from `Config`. Formats use the
+/// `g_date_time_format` syntax.
+#[derive(Debug, Clone)]
+pub struct ClockFormat {
+    pub time_format: String,
+    pub date_format: String,
+    pub show_seconds: bool,
+    pub second_clock: Option<(String, TimeZone)>,
+}
+
+impl ClockFormat {
+    pub fn new(config: &Config) -> Self {
+        let time_format = match &config.clock_time_format {
+            Some(format) => format.clone(),
+            None => match (config.clock_24h, config.clock_seconds) {
+                (true, false) => "%H:%M".to_string(),
+                (true, true) => "%H:%M:%S".to_string(),
+                (false, false) => "%-I:%M %p".to_string(),
+                (false, true) => "%-I:%M:%S %p".to_string(),
+            },
+        };
+        let show_seconds = config.clock_seconds
+            || config
+                .clock_time_format
+                .as_ref()
+                .is_some_and(|format| format.contains("%S") || format.contains("%T"));
+
+        let second_clock = config.second_clock_timezone.as_ref().map(|identifier| {
+            let timezone = TimeZone::new(Some(identifier.as_str()));
+            if timezone.identifier() != identifier.as_str() {
+                warn!(
+                    "Unknown time zone {}, using {}",
+                    identifier,
+                    timezone.identifier()
+                );
+            }
+            let label = config
+                .second_clock_label
+                .clone()
+                .unwrap_or_else(|| identifier.clone());
+            (label, timezone)
+        });
+
+        ClockFormat {
+            time_format,
+            date_format: config.clock_date_format.clone(),
+            show_seconds,
+            second_clock,
+        }
+    }
+
+    pub fn time(&self, now: &DateTime) -> String {
+        now.format(&self.time_format)
+            .map(|s| s.to_string())
+            .unwrap_or_default()
+    }
+
+    pub fn date(&self, now: &DateTime) -> String {
+        now.format(&self.date_format)
+            .map(|s| s.to_string())
+            .unwrap_or_default()
+    }
+
+    pub fn second_clock(&self, now: &DateTime) -> Option<String> {
+        let (label, timezone) = self.second_clock.as_ref()?;
+        let there = now.to_timezone(timezone).ok()?;
+        Some(format!("{} {}", label, self.time(&there)))
+    }
+
+    /// Time until the displayed value changes: the next second when seconds
+    /// are shown, the next minute otherwise.
+    pub fn next_tick(&self, now: &DateTime) -> Duration {
+        let seconds = now.seconds();
+        let remaining = if self.show_seconds {
+            1.0 - seconds.fract()
+        } else {
+            60.0 - seconds
+        };
+
+        Duration::from_secs_f64(remaining.max(0.0)) + TICK_MARGIN
+    }
+}
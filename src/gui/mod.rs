@@ -1 +1,4 @@
+pub mod clock;
+pub mod night;
+pub mod overlay;
 pub mod play_pause_button;
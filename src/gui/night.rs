@@ -0,0 +1,53 @@
+use gtk::glib::DateTime;
+use serde::Deserialize;
+use tracing::warn;
+
+use crate::photo::provider::Config;
+
+#[derive(Deserialize, Default, Debug, Clone, Copy, PartialEq)]
+#[serde(rename_all = "lowercase")]
+pub enum NightMode {
+    /// Only a large dimmed clock, no photos are loaded
+    #[default]
+    Clock,
+    /// Keep the slideshow going, dimmed and tinted warm
+    Dim,
+}
+
+/// Daily period, in minutes since midnight, during which night mode is on.
+/// The end can be earlier than the start for periods that cross midnight.
+#[derive(Debug, Clone, Copy)]
+pub struct NightSchedule {
+    start: u32,
+    end: u32,
+}
+
+impl NightSchedule {
+    /// Returns `None` when no schedule is configured or a time is invalid.
+    pub fn new(config: &Config) -> Option<Self> {
+        let (start, end) = (config.night_start.as_ref()?, config.night_end.as_ref()?);
+        match (parse_time(start), parse_time(end)) {
+            (Some(start), Some(end)) => Some(NightSchedule { start, end }),
+            _ => {
+                warn!("Invalid night mode schedule {} - {}", start, end);
+                None
+            }
+        }
+    }
+
+    pub fn is_night(&self, now: &DateTime) -> bool {
+        let minute = (now.hour() * 60 + now.minute()) as u32;
+        if self.start <= self.end {
+            self.start <= minute && minute < self.end
+        } else {
+            minute >= self.start || minute < self.end
+        }
+    }
+}
+
+/// Parses "HH:MM" into minutes since midnight.
+pub fn parse_time(text: &str) -> Option<u32> {
+    let (hours, minutes) = text.trim().split_once(':')?;
+    let (hours, minutes): (u32, u32) = (hours.parse().ok()?, minutes.parse().ok()?);
+    (hours < 24 && minutes < 60).then_some(hours * 60 + minutes)
+}
@@ -0,0 +1,215 @@
+use gtk::Align;
+use serde::Deserialize;
+
+use crate::photo::saliency::EnergyMap;
+
+/// Mean luminance above which a region counts as bright.
+const BRIGHT_LUMINANCE: f32 = 0.75;
+/// Mean edge energy above which a region counts as busy.
+const BUSY_ENERGY: f32 = 0.12;
+
+#[derive(Deserialize, Debug, Clone, Copy, PartialEq)]
+#[serde(rename_all = "lowercase")]
+pub enum OverlayWidget {
+    /// Current time and date
+    Clock,
+    /// Where and when the photo was taken
+    Location,
+    /// Play/pause button and, while paused, the photo path
+    Pause,
+    /// Small map of where the photo was taken
+    Map,
+    /// Current conditions and a short forecast
+    Weather,
+    /// Upcoming events from ICS calendars
+    Calendar,
+}
+
+impl OverlayWidget {
+    pub const ALL: [OverlayWidget; 6] = [
+        OverlayWidget::Clock,
+        OverlayWidget::Location,
+        OverlayWidget::Pause,
+        OverlayWidget::Map,
+        OverlayWidget::Weather,
+        OverlayWidget::Calendar,
+    ];
+}
+
+#[derive(Deserialize, Debug, Clone, Copy, PartialEq)]
+#[serde(rename_all = "kebab-case")]
+pub enum OverlayPosition {
+    TopLeft,
+    TopRight,
+    BottomLeft,
+    BottomRight,
+}
+
+impl OverlayPosition {
+    /// Horizontal and vertical alignment inside the window
+    pub fn align(&self) -> (Align, Align) {
+        match self {
+            OverlayPosition::TopLeft => (Align::Start, Align::Start),
+            OverlayPosition::TopRight => (Align::End, Align::Start),
+            OverlayPosition::BottomLeft => (Align::Start, Align::End),
+            OverlayPosition::BottomRight => (Align::End, Align::End),
+        }
+    }
+}
+
+#[derive(Deserialize, Default, Debug, Clone, Copy, PartialEq)]
+#[serde(rename_all = "lowercase")]
+pub enum OverlaySize {
+    Small,
+    #[default]
+    Normal,
+    Large,
+}
+
+impl OverlaySize {
+    pub fn css_class(&self) -> Option<&'static str> {
+        match self {
+            OverlaySize::Small => Some("overlay-small"),
+            OverlaySize::Normal => None,
+            OverlaySize::Large => Some("overlay-large"),
+        }
+    }
+}
+
+#[derive(Deserialize, Debug, Clone)]
+pub struct OverlayConfig {
+    pub widget: OverlayWidget,
+    pub position: OverlayPosition,
+    #[serde(default)]
+    pub size: OverlaySize,
+    #[serde(default = "default_opacity")]
+    pub opacity: f64,
+    // Hide the widget while it would cover a bright or busy part of the photo
+    #[serde(default)]
+    pub auto_hide: bool,
+}
+
+fn default_opacity() -> f64 {
+    1.0
+}
+
+/// The layout from before overlays were configurable.
+pub fn default_overlays() -> Vec<OverlayConfig> {
+    [
+        (OverlayWidget::Clock, OverlayPosition::TopRight),
+        (OverlayWidget::Location, OverlayPosition::BottomRight),
+        (OverlayWidget::Pause, OverlayPosition::TopLeft),
+    ]
+    .into_iter()
+    .map(|(widget, position)| OverlayConfig {
+        widget,
+        position,
+        size: OverlaySize::Normal,
+        opacity: default_opacity(),
+        auto_hide: false,
+    })
+    .collect()
+}
+
+/// Whether a region of the photo, given in fractions of its size, is too
+/// bright or busy to draw over.
+pub fn is_cluttered(map: &EnergyMap, x: f64, y: f64, width: f64, height: f64) -> bool {
+    let (luminance, energy) = map.region_stats(x, y, width, height);
+    luminance > BRIGHT_LUMINANCE || energy > BUSY_ENERGY
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn parse(json: &str) -> Result<OverlayConfig, json5::Error> {
+        json5::from_str(json)
+    }
+
+    #[test]
+    fn fills_in_defaults() {
+        let overlay = parse(r#"{ widget: "clock", position: "top-right" }"#).unwrap();
+        assert_eq!(overlay.widget, OverlayWidget::Clock);
+        assert_eq!(overlay.position, OverlayPosition::TopRight);
+        assert_eq!(overlay.size, OverlaySize::Normal);
+        assert_eq!(overlay.opacity, 1.0);
+        assert!(!overlay.auto_hide);
+    }
+
+    #[test]
+    fn parses_every_field() {
+        let overlay = parse(
+            r#"{ widget: "map", position: "bottom-left", size: "large", opacity: 0.5, auto_hide: true }"#,
+        )
+        .unwrap();
+        assert_eq!(overlay.widget, OverlayWidget::Map);
+        assert_eq!(overlay.position, OverlayPosition::BottomLeft);
+        assert_eq!(overlay.size, OverlaySize::Large);
+        assert_eq!(overlay.opacity, 0.5);
+        assert!(overlay.auto_hide);
+    }
+
+    #[test]
+    fn parses_every_widget_and_position() {
+        for (name, widget) in [
+            ("clock", OverlayWidget::Clock),
+            ("location", OverlayWidget::Location),
+            ("pause", OverlayWidget::Pause),
+            ("map", OverlayWidget::Map),
+            ("weather", OverlayWidget::Weather),
+            ("calendar", OverlayWidget::Calendar),
+        ] {
+            let json = format!(r#"{{ widget: "{}", position: "top-left" }}"#, name);
+            assert_eq!(parse(&json).unwrap().widget, widget);
+            assert!(OverlayWidget::ALL.contains(&widget));
+        }
+
+        for (name, position) in [
+            ("top-left", OverlayPosition::TopLeft),
+            ("top-right", OverlayPosition::TopRight),
+            ("bottom-left", OverlayPosition::BottomLeft),
+            ("bottom-right", OverlayPosition::BottomRight),
+        ] {
+            let json = format!(r#"{{ widget: "clock", position: "{}" }}"#, name);
+            assert_eq!(parse(&json).unwrap().position, position);
+        }
+    }
+
+    #[test]
+    fn rejects_unknown_values() {
+        assert!(parse(r#"{ widget: "radio", position: "top-left" }"#).is_err());
+        assert!(parse(r#"{ widget: "clock", position: "center" }"#).is_err());
+        assert!(parse(r#"{ widget: "clock", position: "top_left" }"#).is_err());
+        assert!(parse(r#"{ widget: "clock", position: "top-left", size: "huge" }"#).is_err());
+        assert!(parse(r#"{ widget: "clock" }"#).is_err());
+    }
+
+    #[test]
+    fn default_overlays_keep_the_old_layout() {
+        let overlays = default_overlays();
+        let layout: Vec<(OverlayWidget, OverlayPosition)> = overlays
+            .iter()
+            .map(|overlay| (overlay.widget, overlay.position))
+            .collect();
+        assert_eq!(
+            layout,
+            [
+                (OverlayWidget::Clock, OverlayPosition::TopRight),
+                (OverlayWidget::Location, OverlayPosition::BottomRight),
+                (OverlayWidget::Pause, OverlayPosition::TopLeft),
+            ]
+        );
+        assert!(overlays
+            .iter()
+            .all(|overlay| overlay.size == OverlaySize::Normal
+                && overlay.opacity == 1.0
+                && !overlay.auto_hide));
+    }
+
+    #[test]
+    fn sizes_map_to_css_classes() {
+        assert_eq!(OverlaySize::Small.css_class(), Some("overlay-small"));
+        assert_eq!(OverlaySize::Normal.css_class(), None);
+        assert_eq!(OverlaySize::Large.css_class(), Some("overlay-large"));
+    }
+}
@@ -0,0 +1,129 @@
+use serde_json::{json, Value};
+
+use crate::mqtt::{self, MqttPublisher};
+use crate::photo::provider::{album_names, Config};
+
+/// Publishes retained Home Assistant MQTT discovery configs for the frame's
+/// entities. They use the state topics from `state::publish_to_mqtt` and the
+/// command topics handled as `RemoteCommand`s.
+pub fn publish_discovery(config: &Config, publisher: &MqttPublisher) {
+    let base = &config.mqtt_base_topic;
+    let node_id: String = base
+        .chars()
+        .map(|c| if c.is_ascii_alphanumeric() { c } else { '_' })
+        .collect();
+    let device = json!({
+        "identifiers": [node_id],
+        "name": "Photo Frame",
+        "manufacturer": "Pi Photo Frame",
+        "sw_version": env!("CARGO_PKG_VERSION"),
+    });
+
+    let entities = [
+        (
+            "switch",
+            "display",
+            json!({
+                "name": "Display",
+                "icon": "mdi:monitor",
+                "state_topic": format!("{}/state/display", base),
+                "state_on": "on",
+                "state_off": "off",
+                "command_topic": format!("{}/cmd/display", base),
+            }),
+        ),
+        (
+            "switch",
+            "paused",
+            json!({
+                "name": "Paused",
+                "icon": "mdi:pause",
+                "state_topic": format!("{}/state/playback", base),
+                "state_on": "paused",
+                "state_off": "playing",
+                "command_topic": format!("{}/cmd/paused", base),
+            }),
+        ),
+        (
+            "button",
+            "next",
+            json!({
+                "name": "Next photo",
+                "icon": "mdi:skip-next",
+                "command_topic": format!("{}/cmd/next", base),
+            }),
+        ),
+        (
+            "button",
+            "previous",
+            json!({
+                "name": "Previous photo",
+                "icon": "mdi:skip-previous",
+                "command_topic": format!("{}/cmd/prev", base),
+            }),
+        ),
+        (
+            "select",
+            "album",
+            json!({
+                "name": "Album",
+                "icon": "mdi:image-album",
+                "options": album_names(config),
+                "state_topic": format!("{}/state/album", base),
+                // All paths are published as an empty album
+                "value_template": "{{ value or 'all' }}",
+                "command_topic": format!("{}/cmd/album", base),
+            }),
+        ),
+        (
+            "number",
+            "brightness",
+            json!({
+                "name": "Brightness",
+                "icon": "mdi:brightness-6",
+                "min": 0.0,
+                "max": 1.0,
+                "step": 0.05,
+                "optimistic": true,
+                "command_topic": format!("{}/cmd/brightness", base),
+            }),
+        ),
+        (
+            "sensor",
+            "photo",
+            json!({
+                "name": "Current photo",
+                "icon": "mdi:image",
+                "state_topic": format!("{}/state/photo", base),
+                "json_attributes_topic": format!("{}/state", base),
+            }),
+        ),
+    ];
+
+    for (component, object_id, mut entity) in entities {
+        if let Value::Object(fields) = &mut entity {
+            fields.insert(
+                "unique_id".to_string(),
+                json!(format!("{}_{}", node_id, object_id)),
+            );
+            fields.insert(
+                "object_id".to_string(),
+                json!(format!("{}_{}", node_id, object_id)),
+            );
+            fields.insert(
+                "availability_topic".to_string(),
+                json!(mqtt::availability_topic(config)),
+            );
+            fields.insert("device".to_string(), device.clone());
+        }
+
+        publisher.publish(
+            format!(
+                "{}/{}/{}/{}/config",
+                config.mqtt_discovery_prefix, component, node_id, object_id
+            ),
+            entity.to_string(),
+            true,
+        );
+    }
+}
@@ -0,0 +1,442 @@
+use std::ops::Range;
+
+use serde_json::{Map, Value};
+
+/// Width up to which arrays and objects are written on one line.
+const ONE_LINE_WIDTH: usize = 80;
+
+/// A top-level setting in the config file, as byte offsets into its text.
+struct Entry {
+    key: String,
+    key_start: usize,
+    value: Range<usize>,
+    /// Just past the value, or past its comma when it has one
+    end: usize,
+    has_comma: bool,
+}
+
+/// Finds the top-level settings of a JSON5 object, skipping comments.
+struct Scanner<'a> {
+    text: &'a str,
+    bytes: &'a [u8],
+    pos: usize,
+}
+
+impl<'a> Scanner<'a> {
+    fn new(text: &'a str) -> Self {
+        Scanner {
+            text,
+            bytes: text.as_bytes(),
+            pos: 0,
+        }
+    }
+
+    fn peek(&self) -> Option<u8> {
+        self.bytes.get(self.pos).copied()
+    }
+
+    /// Moves past whitespace and comments.
+    fn skip(&mut self) -> Result<(), String> {
+        loop {
+            match (self.peek(), self.bytes.get(self.pos + 1)) {
+                (Some(c), _) if c.is_ascii_whitespace() => self.pos += 1,
+                (Some(b'/'), Some(b'/')) => {
+                    self.pos = self.text[self.pos..]
+                        .find('\n')
+                        .map_or(self.text.len(), |end| self.pos + end);
+                }
+                (Some(b'/'), Some(b'*')) => {
+                    let end = self.text[self.pos + 2..]
+                        .find("*/")
+                        .ok_or_else(|| "unterminated comment".to_string())?;
+                    self.pos += end + 4;
+                }
+                _ => return Ok(()),
+            }
+        }
+    }
+
+    fn string(&mut self) -> Result<(), String> {
+        let quote = self.bytes[self.pos];
+        self.pos += 1;
+        while let Some(c) = self.peek() {
+            self.pos += 1;
+            match c {
+                b'\\' => self.pos += 1,
+                c if c == quote => return Ok(()),
+                _ => {}
+            }
+        }
+        Err("unterminated string".to_string())
+    }
+
+    fn key(&mut self) -> Result<String, String> {
+        let start = self.pos;
+        if matches!(self.peek(), Some(b'"' | b'\'')) {
+            self.string()?;
+            return json5::from_str(&self.text[start..self.pos]).map_err(|e| e.to_string());
+        }
+
+        while self
+            .peek()
+            .is_some_and(|c| c.is_ascii_alphanumeric() || c == b'_' || c == b'$')
+        {
+            self.pos += 1;
+        }
+        if self.pos == start {
+            return Err(format!("expected a setting name at byte {}", start));
+        }
+        Ok(self.text[start..self.pos].to_string())
+    }
+
+    /// Moves past one value, with everything nested in it.
+    fn value(&mut self) -> Result<(), String> {
+        match self.peek() {
+            Some(b'"' | b'\'') => self.string(),
+            Some(b'{' | b'[') => {
+                let mut depth = 0;
+                loop {
+                    self.skip()?;
+                    match self.peek() {
+                        None => return Err("unterminated object or array".to_string()),
+                        Some(b'"' | b'\'') => {
+                            self.string()?;
+                            continue;
+                        }
+                        Some(b'{' | b'[') => depth += 1,
+                        Some(b'}' | b']') => {
+                            depth -= 1;
+                            if depth == 0 {
+                                self.pos += 1;
+                                return Ok(());
+                            }
+                        }
+                        _ => {}
+                    }
+                    self.pos += 1;
+                }
+            }
+            _ => {
+                let start = self.pos;
+                while self.peek().is_some_and(|c| {
+                    !c.is_ascii_whitespace() && !matches!(c, b',' | b'}' | b']' | b'/')
+                }) {
+                    self.pos += 1;
+                }
+                if self.pos == start {
+                    return Err(format!("expected a value at byte {}", start));
+                }
+                Ok(())
+            }
+        }
+    }
+
+    /// The settings and the position just inside the opening brace.
+    fn entries(&mut self) -> Result<(Vec<Entry>, usize), String> {
+        self.skip()?;
+        if self.peek() != Some(b'{') {
+            return Err("the config file is not an object".to_string());
+        }
+        self.pos += 1;
+        let open = self.pos;
+
+        let mut entries = vec![];
+        loop {
+            self.skip()?;
+            match self.peek() {
+                Some(b'}') => return Ok((entries, open)),
+                None => return Err("unterminated config file".to_string()),
+                _ => {}
+            }
+
+            let key_start = self.pos;
+            let key = self.key()?;
+            self.skip()?;
+            if self.peek() != Some(b':') {
+                return Err(format!("expected : after {}", key));
+            }
+            self.pos += 1;
+            self.skip()?;
+            let value_start = self.pos;
+            self.value()?;
+            let value = value_start..self.pos;
+
+            self.skip()?;
+            let has_comma = self.peek() == Some(b',');
+            if has_comma {
+                self.pos += 1;
+            }
+            entries.push(Entry {
+                key,
+                key_start,
+                end: if has_comma { self.pos } else { value.end },
+                value,
+                has_comma,
+            });
+        }
+    }
+}
+
+/// Writes the settings of `config` that differ from `current` into `text`,
+/// the config file `current` was read from. Comments and the other settings
+/// stay as they are, removed settings are taken out and new ones go at the
+/// end.
+pub fn patch(
+    text: &str,
+    current: &Map<String, Value>,
+    config: &Map<String, Value>,
+) -> Result<String, String> {
+    let (entries, open) = Scanner::new(text).entries()?;
+
+    let mut edits: Vec<(Range<usize>, String)> = vec![];
+    for entry in &entries {
+        let indent = indent(text, entry.key_start);
+        match config.get(&entry.key) {
+            Some(value) if current.get(&entry.key).is_some_and(|old| same(old, value)) => {}
+            Some(value) => edits.push((entry.value.clone(), format_value(value, indent))),
+            None => {
+                // The whole line when nothing else is on it
+                let start = entry.key_start - indent.len();
+                let rest = &text[entry.end..];
+                let end = match rest.find('\n') {
+                    Some(newline) if rest[..newline].trim().is_empty() => entry.end + newline + 1,
+                    _ => entry.end,
+                };
+                edits.push((start..end, String::new()));
+            }
+        }
+    }
+
+    let added: Vec<(&String, &Value)> = config
+        .iter()
+        .filter(|(key, _)| !entries.iter().any(|entry| &entry.key == *key))
+        .collect();
+    if !added.is_empty() {
+        let last = entries
+            .iter()
+            .rev()
+            .find(|entry| config.contains_key(&entry.key));
+        let indent = last.map_or("  ", |last| indent(text, last.key_start));
+        let (at, mut insert) = match last {
+            Some(last) if last.has_comma => (last.end, String::new()),
+            Some(last) => (last.value.end, ",".to_string()),
+            None => (open, String::new()),
+        };
+        for (key, value) in added {
+            insert.push_str(&format!(
+                "\n{}{}: {},",
+                indent,
+                format_key(key),
+                format_value(value, indent)
+            ));
+        }
+        edits.push((at..at, insert));
+    }
+
+    // From the end, so the offsets of the earlier edits stay valid
+    edits.sort_by_key(|(range, _)| std::cmp::Reverse(range.start));
+    let mut text = text.to_string();
+    for (range, replacement) in edits {
+        text.replace_range(range, &replacement);
+    }
+    Ok(text)
+}
+
+/// The whitespace before `pos` on its line, or nothing when other text is
+/// in front of it.
+fn indent(text: &str, pos: usize) -> &str {
+    let line_start = text[..pos].rfind('\n').map_or(0, |newline| newline + 1);
+    let before = &text[line_start..pos];
+    if before.trim().is_empty() {
+        before
+    } else {
+        ""
+    }
+}
+
+fn format_key(key: &str) -> String {
+    let bare = key.chars().next().is_some_and(|c| !c.is_ascii_digit())
+        && key.chars().all(|c| c.is_ascii_alphanumeric() || c == '_');
+    if bare {
+        key.to_string()
+    } else {
+        Value::String(key.to_string()).to_string()
+    }
+}
+
+/// JSON on one line when it is short, indented under the setting otherwise.
+fn format_value(value: &Value, indent: &str) -> String {
+    let line = value.to_string();
+    if line.len() <= ONE_LINE_WIDTH {
+        return line;
+    }
+    serde_json::to_string_pretty(value)
+        .unwrap_or(line)
+        .replace('\n', &format!("\n{}", indent))
+}
+
+/// Equal, with numbers compared by value, since the file may say `1.0` where
+/// the browser sends `1`.
+fn same(a: &Value, b: &Value) -> bool {
+    match (a, b) {
+        (Value::Number(a), Value::Number(b)) => a.as_f64() == b.as_f64(),
+        (Value::Array(a), Value::Array(b)) => {
+            a.len() == b.len() && a.iter().zip(b).all(|(a, b)| same(a, b))
+        }
+        (Value::Object(a), Value::Object(b)) => {
+            a.len() == b.len()
+                && a.iter()
+                    .all(|(key, a)| b.get(key).is_some_and(|b| same(a, b)))
+        }
+        _ => a == b,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use serde_json::json;
+
+    use super::*;
+
+    const TEXT: &str = r#"{
+  paths: ["/photos"],
+  // Transition time in seconds
+  transition_time: 15,
+  /* Use mqtt to start/stop the slideshow */
+  mqtt: true,
+  mqtt_topic: "frame/{cmd}, // not a comment",
+  // http_address: "0.0.0.0:8080",
+  overlays: [
+    { widget: "clock", position: "top-right", opacity: 1.0 },
+  ],
+  'quoted key': 'x',
+  night_brightness: 0.3
+}
+"#;
+
+    fn object(value: Value) -> Map<String, Value> {
+        value.as_object().unwrap().clone()
+    }
+
+    fn current() -> Map<String, Value> {
+        object(json5::from_str(TEXT).unwrap())
+    }
+
+    fn patched(changes: impl FnOnce(&mut Map<String, Value>)) -> String {
+        let mut config = current();
+        changes(&mut config);
+        let text = patch(TEXT, &current(), &config).unwrap();
+        let reread: Value = json5::from_str(&text).unwrap();
+        assert!(same(&reread, &Value::Object(config)), "{}", text);
+        text
+    }
+
+    #[test]
+    fn keeps_the_file_without_changes() {
+        assert_eq!(patched(|_| {}), TEXT);
+    }
+
+    #[test]
+    fn numbers_that_only_look_different_are_unchanged() {
+        let text = patched(|config| {
+            config.insert(
+                "overlays".to_string(),
+                json!([{ "widget": "clock", "position": "top-right", "opacity": 1 }]),
+            );
+        });
+        assert_eq!(text, TEXT);
+    }
+
+    #[test]
+    fn replaces_only_the_changed_value() {
+        let text = patched(|config| {
+            config.insert("transition_time".to_string(), json!(30));
+            config.insert("mqtt_topic".to_string(), json!("frame/cmd"));
+        });
+        assert_eq!(
+            text,
+            TEXT.replace("transition_time: 15", "transition_time: 30")
+                .replace(r#""frame/{cmd}, // not a comment""#, r#""frame/cmd""#)
+        );
+        assert!(text.contains("// Transition time in seconds"));
+        assert!(text.contains("/* Use mqtt to start/stop the slideshow */"));
+    }
+
+    #[test]
+    fn replaces_nested_values() {
+        let text = patched(|config| {
+            config.insert(
+                "overlays".to_string(),
+                json!([{ "widget": "map", "position": "bottom-left" }]),
+            );
+        });
+        assert!(text.contains(r#"overlays: [{"position":"bottom-left","widget":"map"}],"#));
+        assert!(text.contains("  night_brightness: 0.3\n"));
+    }
+
+    #[test]
+    fn writes_long_values_over_several_lines() {
+        let sources: Vec<String> = (0..5)
+            .map(|i| format!("https://example.com/calendar-{}.ics", i))
+            .collect();
+        let text = patched(|config| {
+            config.insert("paths".to_string(), json!(sources));
+        });
+        assert!(text.contains("  paths: [\n    \"https://example.com/calendar-0.ics\",\n"));
+        assert!(text.contains("\n  ],\n  // Transition time in seconds"));
+    }
+
+    #[test]
+    fn removes_settings() {
+        let text = patched(|config| {
+            config.remove("mqtt");
+            config.remove("quoted key");
+        });
+        assert!(!text.contains("mqtt: true"));
+        assert!(!text.contains("quoted key"));
+        // The comment above a removed setting stays
+        assert!(text.contains("/* Use mqtt to start/stop the slideshow */\n  mqtt_topic"));
+    }
+
+    #[test]
+    fn adds_settings_at_the_end() {
+        let text = patched(|config| {
+            config.insert("http_address".to_string(), json!("0.0.0.0:8080"));
+            config.insert("2fa".to_string(), json!(false));
+        });
+        assert!(text.ends_with(
+            "  night_brightness: 0.3,\n  \"2fa\": false,\n  http_address: \"0.0.0.0:8080\",\n}\n"
+        ));
+        assert!(text.contains("// http_address: \"0.0.0.0:8080\","));
+    }
+
+    #[test]
+    fn adds_settings_after_a_removed_last_one() {
+        let text = patched(|config| {
+            config.remove("night_brightness");
+            config.insert("night_warmth".to_string(), json!(0.6));
+        });
+        assert!(text.ends_with("  'quoted key': 'x',\n  night_warmth: 0.6,\n}\n"));
+    }
+
+    #[test]
+    fn adds_settings_to_an_empty_file() {
+        let config = object(json!({ "mqtt": false }));
+        let text = patch("{}", &Map::new(), &config).unwrap();
+        assert_eq!(text, "{\n  mqtt: false,}");
+        assert_eq!(
+            json5::from_str::<Value>(&text).unwrap(),
+            json!({ "mqtt": false })
+        );
+    }
+
+    #[test]
+    fn rejects_broken_files() {
+        let config = Map::new();
+        assert!(patch("[1, 2]", &config, &config).is_err());
+        assert!(patch("{ mqtt: true", &config, &config).is_err());
+        assert!(patch("{ mqtt true }", &config, &config).is_err());
+        assert!(patch("{ topic: \"open }", &config, &config).is_err());
+        assert!(patch("{ /* open", &config, &config).is_err());
+    }
+}
@@ -0,0 +1,244 @@
+use std::convert::Infallible;
+use std::net::SocketAddr;
+use std::time::Duration;
+
+use axum::extract::{Path, Query, State};
+use axum::http::{header, Request, StatusCode};
+use axum::middleware::{self, Next};
+use axum::response::sse::{Event, KeepAlive, Sse};
+use axum::response::{IntoResponse, Response};
+use axum::routing::{get, post};
+use axum::{Json, Router};
+use serde::{Deserialize, Serialize};
+use tokio::sync::mpsc::UnboundedSender;
+use tokio_stream::wrappers::BroadcastStream;
+use tokio_stream::{Stream, StreamExt};
+use tracing::{debug, warn};
+
+use crate::events;
+use crate::photo::provider::{album_names, Config};
+use crate::remote::RemoteCommand;
+use crate::state::current_state;
+
+mod config_file;
+mod web;
+
+pub struct HttpSettings {
+    address: SocketAddr,
+    token: Option<String>,
+    /// Names and paths accepted by `MediaProvider::set_album`
+    albums: Vec<String>,
+}
+
+impl HttpSettings {
+    /// Returns `None` when the API is off.
+    pub fn new(config: &Config) -> Option<Self> {
+        let address = config.http_address.as_ref()?;
+        let address = match address.parse() {
+            Ok(address) => address,
+            Err(e) => {
+                warn!("Invalid http_address {}, {}", address, e);
+                return None;
+            }
+        };
+
+        Some(HttpSettings {
+            address,
+            token: config.http_token.clone().filter(|token| !token.is_empty()),
+            albums: album_names(config)
+                .into_iter()
+                .chain(config.paths.iter().cloned())
+                .collect(),
+        })
+    }
+}
+
+#[derive(Clone)]
+struct ApiState {
+    token: Option<String>,
+    albums: Vec<String>,
+    commands: UnboundedSender<RemoteCommand>,
+}
+
+impl ApiState {
+    fn send(&self, command: RemoteCommand) -> Response {
+        match self.commands.send(command) {
+            Ok(()) => StatusCode::ACCEPTED.into_response(),
+            Err(_) => StatusCode::SERVICE_UNAVAILABLE.into_response(),
+        }
+    }
+}
+
+#[derive(Deserialize)]
+struct TokenQuery {
+    token: Option<String>,
+}
+
+#[derive(Deserialize)]
+struct AlbumRequest {
+    album: Option<String>,
+}
+
+#[derive(Serialize)]
+struct Photo {
+    path: String,
+    date: Option<String>,
+    location: Option<String>,
+    caption: Option<String>,
+    camera: Option<String>,
+}
+
+#[derive(Serialize)]
+struct Error {
+    error: String,
+}
+
+fn error(status: StatusCode, error: String) -> Response {
+    (status, Json(Error { error })).into_response()
+}
+
+/// Serves the API until the server fails. Commands are handled by the
+/// window, so most endpoints answer 202 before they take effect.
+pub async fn run(settings: HttpSettings, commands: UnboundedSender<RemoteCommand>) {
+    let state = ApiState {
+        token: settings.token,
+        albums: settings.albums,
+        commands,
+    };
+
+    let mut app = Router::new()
+        .route("/api/status", get(status))
+        .route("/api/photo", get(photo))
+        .route("/api/events", get(event_stream))
+        .route("/api/next", post(next))
+        .route("/api/previous", post(previous))
+        .route("/api/pause", post(pause))
+        .route("/api/resume", post(resume))
+        .route("/api/album", post(album))
+        .route("/api/reload", post(reload))
+        .route("/api/commands/:name", post(command));
+    // The web UI edits the config and writes files, never without a token
+    if state.token.is_some() {
+        app = app.merge(web::routes());
+    } else {
+        warn!("The web UI is off, it needs http_token");
+    }
+    let app = app
+        .route_layer(middleware::from_fn_with_state(state.clone(), authorize))
+        .with_state(state);
+
+    let server = match axum::Server::try_bind(&settings.address) {
+        Ok(server) => server,
+        Err(e) => {
+            warn!(
+                "Failed to start the HTTP API on {}, {}",
+                settings.address, e
+            );
+            return;
+        }
+    };
+    debug!("HTTP API listening on {}", settings.address);
+    if let Err(e) = server.serve(app.into_make_service()).await {
+        warn!("HTTP API stopped, {}", e);
+    }
+}
+
+/// Checks `Authorization: Bearer <token>`, or `?token=` for links.
+async fn authorize<B>(
+    State(state): State<ApiState>,
+    Query(query): Query<TokenQuery>,
+    request: Request<B>,
+    next: Next<B>,
+) -> Response {
+    let Some(token) = &state.token else {
+        return next.run(request).await;
+    };
+
+    let bearer = request
+        .headers()
+        .get(header::AUTHORIZATION)
+        .and_then(|value| value.to_str().ok())
+        .and_then(|value| value.strip_prefix("Bearer "));
+    if bearer == Some(token.as_str()) || query.token.as_ref() == Some(token) {
+        next.run(request).await
+    } else {
+        error(StatusCode::UNAUTHORIZED, "invalid token".to_string())
+    }
+}
+
+async fn status() -> Response {
+    Json(current_state()).into_response()
+}
+
+async fn photo() -> Response {
+    let state = current_state();
+    match state.photo {
+        Some(path) => Json(Photo {
+            path,
+            date: state.date,
+            location: state.location,
+            caption: state.caption,
+            camera: state.camera,
+        })
+        .into_response(),
+        None => error(StatusCode::NOT_FOUND, "no photo is shown".to_string()),
+    }
+}
+
+/// Server-sent events, starting with the whole state as a `state` event.
+/// Events a slow client missed are skipped.
+async fn event_stream() -> Sse<impl Stream<Item = Result<Event, Infallible>>> {
+    let receiver = events::subscribe();
+    let initial = Event::default()
+        .event("state")
+        .json_data(current_state())
+        .ok();
+    let events = BroadcastStream::new(receiver).filter_map(|event| {
+        let event = event.ok()?;
+        Event::default().event(event.name()).json_data(&event).ok()
+    });
+
+    let stream = tokio_stream::iter(initial).chain(events).map(Ok);
+    Sse::new(stream).keep_alive(KeepAlive::new().interval(Duration::from_secs(15)))
+}
+
+async fn next(State(state): State<ApiState>) -> Response {
+    state.send(RemoteCommand::Next)
+}
+
+async fn previous(State(state): State<ApiState>) -> Response {
+    state.send(RemoteCommand::Previous)
+}
+
+async fn pause(State(state): State<ApiState>) -> Response {
+    state.send(RemoteCommand::SetPaused(true))
+}
+
+async fn resume(State(state): State<ApiState>) -> Response {
+    state.send(RemoteCommand::SetPaused(false))
+}
+
+async fn album(State(state): State<ApiState>, Json(request): Json<AlbumRequest>) -> Response {
+    if let Some(album) = request.album.as_ref().filter(|album| !album.is_empty()) {
+        if !state.albums.contains(album) {
+            return error(StatusCode::BAD_REQUEST, format!("unknown album {}", album));
+        }
+    }
+    state.send(RemoteCommand::Album(request.album))
+}
+
+async fn reload(State(state): State<ApiState>) -> Response {
+    state.send(RemoteCommand::ReloadConfig)
+}
+
+/// Any command from `RemoteCommand::parse`, with the same payloads as MQTT.
+async fn command(
+    State(state): State<ApiState>,
+    Path(name): Path<String>,
+    body: axum::body::Bytes,
+) -> Response {
+    match RemoteCommand::parse(&name, &body) {
+        Ok(command) => state.send(command),
+        Err(e) => error(StatusCode::BAD_REQUEST, e),
+    }
+}
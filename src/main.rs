@@ -1,16 +1,27 @@
+use std::ffi::CString;
 use std::io;
 
 use gtk::gio::{self, prelude::*};
 use gtk::glib;
 use once_cell::sync::Lazy;
 use photo::provider::{load_config, load_failed_files};
-use tracing::Level;
+use tracing::{warn, Level};
 
+mod calendar;
 mod config;
+mod dbus;
+mod display;
+mod events;
 mod geocoder;
 mod gui;
+mod homeassistant;
+mod http;
+mod mqtt;
 mod photo;
+mod remote;
+mod state;
 mod utils;
+mod weather;
 mod window;
 
 mod application;
@@ -44,5 +55,28 @@ fn main() -> glib::ExitCode {
         .with_writer(io::stdout)
         .init();
 
+    if let Some(locale) = &config.clock_locale {
+        set_time_locale(locale);
+    }
+
     PpfApplication::new(config, failed_files).run()
 }
+
+/// Day and month names from `locale`, everything else from the environment.
+/// GTK would set the whole locale from the environment when it starts, so
+/// that is done here instead.
+fn set_time_locale(locale: &str) {
+    let Ok(locale) = CString::new(locale) else {
+        warn!("Invalid clock_locale {:?}", locale);
+        return;
+    };
+
+    gtk::disable_setlocale();
+    // SAFETY: called before GTK starts, nothing reads the locale yet
+    unsafe {
+        libc::setlocale(libc::LC_ALL, c"".as_ptr());
+        if libc::setlocale(libc::LC_TIME, locale.as_ptr()).is_null() {
+            warn!("Locale {:?} is not available", locale);
+        }
+    }
+}
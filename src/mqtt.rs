@@ -0,0 +1,335 @@
+use std::collections::HashMap;
+use std::fs;
+use std::time::Duration;
+
+use rumqttc::{
+    Event::Incoming,
+    LastWill, MqttOptions,
+    Packet::{ConnAck, Publish},
+    QoS, TlsConfiguration, Transport,
+};
+use serde::Deserialize;
+use tokio::sync::mpsc::{self, UnboundedSender};
+use tracing::{debug, warn};
+
+use crate::photo::provider::Config;
+use crate::spawn_tokio;
+
+/// "online" while connected, "offline" from the broker's last will.
+const ONLINE: &str = "online";
+const OFFLINE: &str = "offline";
+
+#[derive(Deserialize, Default, Debug, Clone, Copy, PartialEq)]
+#[serde(rename_all = "lowercase")]
+pub enum MqttTransport {
+    #[default]
+    Tcp,
+    /// MQTT over WebSockets, e.g. through a reverse proxy
+    Websocket,
+}
+
+/// Everything needed to connect again after the connection drops.
+#[derive(Clone)]
+struct MqttSettings {
+    /// A ws:// or wss:// URL for WebSockets
+    host: String,
+    port: u16,
+    client_id: String,
+    credentials: Option<(String, String)>,
+    keep_alive: Duration,
+    qos: QoS,
+    transport: MqttTransport,
+    /// CA and optional client certificate and key, all PEM
+    tls: Option<(Vec<u8>, Option<(Vec<u8>, Vec<u8>)>)>,
+    /// Without a CA file the system certificates are used
+    tls_system_roots: bool,
+    availability_topic: String,
+}
+
+impl MqttSettings {
+    fn new(config: &Config) -> Result<Self, String> {
+        let read = |path: &String| fs::read(path).map_err(|e| format!("{}: {}", path, e));
+        let client_auth = match (&config.mqtt_client_cert, &config.mqtt_client_key) {
+            (Some(cert), Some(key)) => Some((read(cert)?, read(key)?)),
+            (None, None) => None,
+            _ => return Err("mqtt_client_cert and mqtt_client_key go together".to_string()),
+        };
+        let tls = match &config.mqtt_ca_file {
+            Some(ca_file) => Some((read(ca_file)?, client_auth)),
+            None if client_auth.is_some() => {
+                return Err("mqtt_ca_file is needed for client certificates".to_string())
+            }
+            None => None,
+        };
+        let tls_enabled = config.mqtt_tls || tls.is_some();
+
+        let port = config
+            .mqtt_port
+            .unwrap_or(match (config.mqtt_transport, tls_enabled) {
+                (MqttTransport::Tcp, false) => 1883,
+                (MqttTransport::Tcp, true) => 8883,
+                (MqttTransport::Websocket, false) => 80,
+                (MqttTransport::Websocket, true) => 443,
+            });
+        let host = match config.mqtt_transport {
+            MqttTransport::Tcp => config.mqtt_host.clone(),
+            MqttTransport::Websocket => format!(
+                "{}://{}:{}{}",
+                if tls_enabled { "wss" } else { "ws" },
+                config.mqtt_host,
+                port,
+                config.mqtt_websocket_path
+            ),
+        };
+
+        Ok(MqttSettings {
+            host,
+            port,
+            client_id: config
+                .mqtt_client_id
+                .clone()
+                .unwrap_or_else(default_client_id),
+            credentials: config.mqtt_user.clone().zip(config.mqtt_password.clone()),
+            keep_alive: Duration::from_secs(config.mqtt_keep_alive_seconds.max(1) as u64),
+            qos: match config.mqtt_qos {
+                0 => QoS::AtMostOnce,
+                1 => QoS::AtLeastOnce,
+                2 => QoS::ExactlyOnce,
+                qos => return Err(format!("mqtt_qos must be 0, 1 or 2, not {}", qos)),
+            },
+            transport: config.mqtt_transport,
+            tls_system_roots: tls_enabled && tls.is_none(),
+            tls,
+            availability_topic: availability_topic(config),
+        })
+    }
+
+    fn transport(&self) -> Option<Transport> {
+        let tls = match &self.tls {
+            Some((ca, client_auth)) => TlsConfiguration::Simple {
+                ca: ca.clone(),
+                alpn: None,
+                client_auth: client_auth.clone(),
+            },
+            None if self.tls_system_roots => TlsConfiguration::default(),
+            None => {
+                return match self.transport {
+                    MqttTransport::Tcp => None,
+                    MqttTransport::Websocket => Some(Transport::Ws),
+                }
+            }
+        };
+
+        Some(match self.transport {
+            MqttTransport::Tcp => Transport::Tls(tls),
+            MqttTransport::Websocket => Transport::Wss(tls),
+        })
+    }
+}
+
+/// Unique per frame, so that two frames on one broker don't kick each other
+/// off.
+fn default_client_id() -> String {
+    match fs::read_to_string("/proc/sys/kernel/hostname") {
+        Ok(hostname) if !hostname.trim().is_empty() => {
+            format!("pi-photo-frame-{}", hostname.trim())
+        }
+        _ => "pi-photo-frame".to_string(),
+    }
+}
+
+#[derive(Debug, Clone)]
+struct Message {
+    topic: String,
+    payload: Vec<u8>,
+    retain: bool,
+}
+
+/// Sends messages through the connection made by [`start`].
+#[derive(Debug, Clone)]
+pub struct MqttPublisher(UnboundedSender<Message>);
+
+impl MqttPublisher {
+    /// Retained messages are published again after reconnecting, the last
+    /// one per topic.
+    pub fn publish(&self, topic: String, payload: impl Into<Vec<u8>>, retain: bool) {
+        let _ = self.0.send(Message {
+            topic,
+            payload: payload.into(),
+            retain,
+        });
+    }
+}
+
+pub fn availability_topic(config: &Config) -> String {
+    format!("{}/availability", config.mqtt_base_topic)
+}
+
+/// Connects to the broker on the tokio runtime and calls `handler` with the
+/// topic and payload of every message on `topics`. Reconnects with
+/// exponential backoff when the connection drops.
+pub fn start<F>(config: &Config, topics: Vec<String>, handler: F) -> MqttPublisher
+where
+    F: Fn(&str, &[u8]) + Send + 'static,
+{
+    let (publisher, mut outgoing) = mpsc::unbounded_channel::<Message>();
+    let settings = match MqttSettings::new(config) {
+        Ok(settings) => settings,
+        Err(e) => {
+            warn!("Not connecting to MQTT, {}", e);
+            return MqttPublisher(publisher);
+        }
+    };
+
+    let (mut client, mut eventloop) = connect_mqtt_async(&settings);
+
+    spawn_tokio!(async move {
+        let mut retained: HashMap<String, Message> = HashMap::new();
+        let mut retry_count = 0;
+        let max_retries = 100;
+        let base_delay = Duration::from_secs(1);
+        let max_delay = Duration::from_secs(60);
+
+        if let Err(e) = subscribe_mqtt_async(&mut client, &topics, settings.qos).await {
+            warn!("Failed to subscribe to topics: {}", e);
+        }
+
+        'connection: loop {
+            let event = tokio::select! {
+                event = eventloop.poll() => event,
+                Some(message) = outgoing.recv() => {
+                    // Never wait here, the event loop isn't polled meanwhile
+                    if let Err(e) = client.try_publish(
+                        &message.topic,
+                        settings.qos,
+                        message.retain,
+                        message.payload.clone(),
+                    ) {
+                        warn!("Failed to publish to {}: {}", message.topic, e);
+                    }
+                    if message.retain {
+                        retained.insert(message.topic.clone(), message);
+                    }
+                    continue 'connection;
+                }
+            };
+
+            match event {
+                Ok(notification) => {
+                    debug!(?notification, "MQTT event");
+                    match notification {
+                        Incoming(Publish(notification)) => {
+                            if topics
+                                .iter()
+                                .any(|filter| rumqttc::matches(&notification.topic, filter))
+                            {
+                                handler(&notification.topic, &notification.payload);
+                            }
+                        }
+                        Incoming(ConnAck(_)) => {
+                            let online = Message {
+                                topic: settings.availability_topic.clone(),
+                                payload: ONLINE.into(),
+                                retain: true,
+                            };
+                            for message in std::iter::once(&online).chain(retained.values()) {
+                                if let Err(e) = client.try_publish(
+                                    &message.topic,
+                                    settings.qos,
+                                    true,
+                                    message.payload.clone(),
+                                ) {
+                                    warn!("Failed to publish to {}: {}", message.topic, e);
+                                }
+                            }
+                        }
+                        _ => {}
+                    }
+
+                    retry_count = 0;
+                }
+
+                Err(e) => {
+                    warn!("Error in eventloop: {}", e);
+
+                    // Disconnect the client
+                    if let Err(e) = client.disconnect().await {
+                        warn!("Error disconnecting: {}", e);
+                    }
+
+                    retry_count += 1;
+
+                    if retry_count > max_retries {
+                        warn!("Maximum retry attempts reached. Exiting.");
+                        break 'connection;
+                    }
+
+                    // Calculate exponential backoff with jitter
+                    let delay =
+                        std::cmp::min(base_delay * 2u32.pow(retry_count as u32 - 1), max_delay);
+
+                    // Add jitter: randomly adjust delay by ±25%
+                    let jitter = delay.as_millis() as f64 * (rand::random::<f64>() * 0.5 - 0.25);
+                    let delay_with_jitter =
+                        Duration::from_millis((delay.as_millis() as f64 + jitter) as u64);
+
+                    debug!(
+                        "Retry attempt {} of {}. Waiting for {:?} before reconnecting",
+                        retry_count, max_retries, delay_with_jitter
+                    );
+
+                    tokio::time::sleep(delay_with_jitter).await;
+
+                    // Attempt to reconnect
+                    let (new_client, new_eventloop) = connect_mqtt_async(&settings);
+                    client = new_client;
+                    eventloop = new_eventloop;
+
+                    // Resubscribe
+                    if let Err(e) = subscribe_mqtt_async(&mut client, &topics, settings.qos).await {
+                        warn!("Error resubscribing to topics: {}", e);
+                        continue;
+                    }
+
+                    debug!("Successfully reconnected");
+                }
+            }
+        }
+    });
+
+    MqttPublisher(publisher)
+}
+
+fn connect_mqtt_async(settings: &MqttSettings) -> (rumqttc::AsyncClient, rumqttc::EventLoop) {
+    let mut mqtt_options = MqttOptions::new(&settings.client_id, &settings.host, settings.port);
+    mqtt_options.set_keep_alive(settings.keep_alive);
+    mqtt_options.set_clean_session(false);
+    mqtt_options.set_last_will(LastWill::new(
+        &settings.availability_topic,
+        OFFLINE,
+        settings.qos,
+        true,
+    ));
+    if let Some((username, password)) = &settings.credentials {
+        mqtt_options.set_credentials(username, password);
+    }
+    if let Some(transport) = settings.transport() {
+        mqtt_options.set_transport(transport);
+    }
+
+    // Room for the retained state published again after reconnecting
+    let (client, eventloop) = rumqttc::AsyncClient::new(mqtt_options, 64);
+
+    (client, eventloop)
+}
+
+async fn subscribe_mqtt_async(
+    client: &mut rumqttc::AsyncClient,
+    topics: &[String],
+    qos: QoS,
+) -> Result<(), rumqttc::ClientError> {
+    for topic in topics {
+        client.subscribe(topic, qos).await?;
+    }
+    Ok(())
+}
@@ -0,0 +1,201 @@
+use std::sync::Arc;
+
+use gtk::gdk_pixbuf::{Colorspace, InterpType, Pixbuf};
+use serde::Deserialize;
+
+use crate::utils::unsafe_wrapper::UnsafeSendSync;
+
+/// Width of the downscaled copy that gets blurred. The blur is cheap at this
+/// size and the result is scaled back up before being shown.
+const BLUR_SAMPLE_WIDTH: i32 = 96;
+const BLUR_OUTPUT_WIDTH: i32 = 480;
+const BLUR_RADIUS: usize = 4;
+const BLUR_PASSES: usize = 3;
+const BLUR_DARKEN: f32 = 0.55;
+
+#[derive(Deserialize, Default, Debug, Clone, Copy, PartialEq)]
+#[serde(rename_all = "lowercase")]
+pub enum BackgroundFill {
+    /// Plain `background_color` behind the photo
+    #[default]
+    Color,
+    /// The most common colour of the photo
+    Dominant,
+    /// A blurred, darkened and scaled-up copy of the photo
+    Blur,
+}
+
+pub enum Background {
+    None,
+    Color(u8, u8, u8),
+    Image(Arc<UnsafeSendSync<Pixbuf>>),
+}
+
+impl Background {
+    pub fn for_photo(fill: BackgroundFill, pixbuf: &Pixbuf, screen_size: (i32, i32)) -> Self {
+        match fill {
+            BackgroundFill::Color => Background::None,
+            BackgroundFill::Dominant => {
+                let (r, g, b) = dominant_color(pixbuf);
+                Background::Color(r, g, b)
+            }
+            BackgroundFill::Blur => match blurred_background(pixbuf, screen_size) {
+                Some(p) => Background::Image(Arc::new(UnsafeSendSync::new(p))),
+                None => Background::None,
+            },
+        }
+    }
+}
+
+/// Returns the average colour of the most populated bucket of a coarse colour
+/// histogram.
+pub fn dominant_color(pixbuf: &Pixbuf) -> (u8, u8, u8) {
+    let Some(small) = pixbuf.scale_simple(32, 32, InterpType::Bilinear) else {
+        return (0, 0, 0);
+    };
+    let pixels = rgb_pixels(&small);
+
+    // 4 bits per channel
+    let mut counts = vec![0u32; 4096];
+    let mut sums = vec![(0u32, 0u32, 0u32); 4096];
+    for p in pixels.chunks_exact(3) {
+        let bucket =
+            ((p[0] as usize >> 4) << 8) | ((p[1] as usize >> 4) << 4) | (p[2] as usize >> 4);
+        counts[bucket] += 1;
+        sums[bucket].0 += p[0] as u32;
+        sums[bucket].1 += p[1] as u32;
+        sums[bucket].2 += p[2] as u32;
+    }
+
+    let (bucket, count) = counts
+        .iter()
+        .enumerate()
+        .max_by_key(|(_, c)| **c)
+        .map(|(b, c)| (b, *c))
+        .unwrap_or((0, 0));
+
+    if count == 0 {
+        return (0, 0, 0);
+    }
+
+    let (r, g, b) = sums[bucket];
+    ((r / count) as u8, (g / count) as u8, (b / count) as u8)
+}
+
+/// Crops the photo to the screen aspect ratio, blurs and darkens it. The
+/// result is meant to be stretched over the whole window.
+pub fn blurred_background(pixbuf: &Pixbuf, screen_size: (i32, i32)) -> Option<Pixbuf> {
+    let (screen_width, screen_height) = screen_size;
+    if screen_width <= 0 || screen_height <= 0 {
+        return None;
+    }
+
+    let (width, height) = (pixbuf.width(), pixbuf.height());
+    let screen_aspect = screen_width as f64 / screen_height as f64;
+    let (crop_width, crop_height) = if width as f64 / height as f64 > screen_aspect {
+        ((height as f64 * screen_aspect) as i32, height)
+    } else {
+        (width, (width as f64 / screen_aspect) as i32)
+    };
+    if crop_width <= 0 || crop_height <= 0 {
+        return None;
+    }
+
+    let cropped = pixbuf.new_subpixbuf(
+        (width - crop_width) / 2,
+        (height - crop_height) / 2,
+        crop_width,
+        crop_height,
+    );
+
+    let sample_height = ((BLUR_SAMPLE_WIDTH as f64 / screen_aspect) as i32).max(1);
+    let small = cropped.scale_simple(BLUR_SAMPLE_WIDTH, sample_height, InterpType::Bilinear)?;
+
+    let mut pixels = rgb_pixels(&small);
+    let (w, h) = (BLUR_SAMPLE_WIDTH as usize, sample_height as usize);
+    for _ in 0..BLUR_PASSES {
+        box_blur(&mut pixels, w, h, BLUR_RADIUS);
+    }
+    for p in pixels.iter_mut() {
+        *p = (*p as f32 * BLUR_DARKEN) as u8;
+    }
+
+    let blurred = Pixbuf::from_mut_slice(
+        pixels,
+        Colorspace::Rgb,
+        false,
+        8,
+        w as i32,
+        h as i32,
+        w as i32 * 3,
+    );
+
+    let output_height = ((BLUR_OUTPUT_WIDTH as f64 / screen_aspect) as i32).max(1);
+    blurred.scale_simple(BLUR_OUTPUT_WIDTH, output_height, InterpType::Bilinear)
+}
+
+/// Copies the pixbuf into a tightly packed RGB buffer, dropping alpha and
+/// row padding.
+pub fn rgb_pixels(pixbuf: &Pixbuf) -> Vec<u8> {
+    let bytes = pixbuf.read_pixel_bytes();
+    let channels = pixbuf.n_channels() as usize;
+    let rowstride = pixbuf.rowstride() as usize;
+    let (width, height) = (pixbuf.width() as usize, pixbuf.height() as usize);
+
+    let mut pixels = Vec::with_capacity(width * height * 3);
+    for y in 0..height {
+        let row = &bytes[y * rowstride..];
+        for x in 0..width {
+            pixels.extend_from_slice(&row[x * channels..x * channels + 3]);
+        }
+    }
+    pixels
+}
+
+/// Horizontal then vertical box blur over a packed RGB buffer.
+fn box_blur(pixels: &mut [u8], width: usize, height: usize, radius: usize) {
+    let mut scratch = pixels.to_vec();
+    blur_pass(pixels, &mut scratch, width, height, radius, true);
+    blur_pass(&scratch, pixels, width, height, radius, false);
+}
+
+fn blur_pass(
+    src: &[u8],
+    dst: &mut [u8],
+    width: usize,
+    height: usize,
+    radius: usize,
+    horizontal: bool,
+) {
+    let (lines, len) = if horizontal {
+        (height, width)
+    } else {
+        (width, height)
+    };
+    let index = |line: usize, i: usize| {
+        if horizontal {
+            (line * width + i) * 3
+        } else {
+            (i * width + line) * 3
+        }
+    };
+
+    for line in 0..lines {
+        for i in 0..len {
+            let start = i.saturating_sub(radius);
+            let end = (i + radius).min(len - 1);
+            let mut sum = [0u32; 3];
+            for j in start..=end {
+                let idx = index(line, j);
+                sum[0] += src[idx] as u32;
+                sum[1] += src[idx + 1] as u32;
+                sum[2] += src[idx + 2] as u32;
+            }
+            let n = (end - start + 1) as u32;
+            let idx = index(line, i);
+            dst[idx] = (sum[0] / n) as u8;
+            dst[idx + 1] = (sum[1] / n) as u8;
+            dst[idx + 2] = (sum[2] / n) as u8;
+        }
+    }
+}
@@ -0,0 +1,124 @@
+use exif::{Exif, In, Tag, Value};
+use serde::Deserialize;
+
+#[derive(Deserialize, Default, Debug, Clone, Copy, PartialEq)]
+#[serde(rename_all = "lowercase")]
+pub enum CameraInfoMode {
+    /// Never show the camera info panel
+    #[default]
+    Off,
+    /// Show it while the slideshow is paused
+    Paused,
+    /// Show it all the time
+    Always,
+}
+
+/// Camera and exposure settings of a photo, as recorded in its EXIF data.
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct CameraInfo {
+    pub make: Option<String>,
+    pub model: Option<String>,
+    pub lens: Option<String>,
+    /// Millimetres
+    pub focal_length: Option<f64>,
+    pub aperture: Option<f64>,
+    /// Seconds
+    pub exposure_time: Option<f64>,
+    pub iso: Option<u32>,
+    pub dimensions: Option<(u32, u32)>,
+}
+
+impl CameraInfo {
+    /// Returns `None` when the photo has none of the fields.
+    pub fn from_exif(exif: &Exif) -> Option<Self> {
+        let ascii = |tag: Tag| match exif.get_field(tag, In::PRIMARY)?.value {
+            Value::Ascii(ref v) if !v.is_empty() => {
+                let text = String::from_utf8_lossy(&v[0]).trim().to_string();
+                (!text.is_empty()).then_some(text)
+            }
+            _ => None,
+        };
+        let rational = |tag: Tag| match exif.get_field(tag, In::PRIMARY)?.value {
+            Value::Rational(ref v) if !v.is_empty() && v[0].denom != 0 => Some(v[0].to_f64()),
+            _ => None,
+        };
+        let uint = |tag: Tag| exif.get_field(tag, In::PRIMARY)?.value.get_uint(0);
+
+        let info = CameraInfo {
+            make: ascii(Tag::Make),
+            model: ascii(Tag::Model),
+            lens: ascii(Tag::LensModel),
+            focal_length: rational(Tag::FocalLength).filter(|f| *f > 0.0),
+            aperture: rational(Tag::FNumber).filter(|f| *f > 0.0),
+            exposure_time: rational(Tag::ExposureTime).filter(|t| *t > 0.0),
+            iso: uint(Tag::PhotographicSensitivity).filter(|iso| *iso > 0),
+            dimensions: uint(Tag::PixelXDimension).zip(uint(Tag::PixelYDimension)),
+        };
+
+        (info != CameraInfo::default()).then_some(info)
+    }
+
+    /// Camera and lens, e.g. "Canon EOS R6, RF24-105mm F4 L IS USM".
+    pub fn camera(&self) -> Option<String> {
+        let camera = match (&self.make, &self.model) {
+            // Most models already start with the make
+            (Some(make), Some(model)) if model.starts_with(make.as_str()) => Some(model.clone()),
+            (Some(make), Some(model)) => Some(format!("{} {}", make, model)),
+            (make, model) => make.clone().or_else(|| model.clone()),
+        };
+
+        match (camera, &self.lens) {
+            (Some(camera), Some(lens)) => Some(format!("{}, {}", camera, lens)),
+            (camera, lens) => camera.or_else(|| lens.clone()),
+        }
+    }
+
+    /// Exposure settings, e.g. "50 mm  f/4  1/250 s  ISO 400  6000 × 4000".
+    pub fn exposure(&self) -> Option<String> {
+        let mut parts = vec![];
+        if let Some(focal_length) = self.focal_length {
+            parts.push(format!("{} mm", trim_decimals(focal_length)));
+        }
+        if let Some(aperture) = self.aperture {
+            parts.push(format!("f/{}", trim_decimals(aperture)));
+        }
+        if let Some(exposure_time) = self.exposure_time {
+            parts.push(shutter_speed(exposure_time));
+        }
+        if let Some(iso) = self.iso {
+            parts.push(format!("ISO {}", iso));
+        }
+        if let Some((width, height)) = self.dimensions {
+            parts.push(format!("{} × {}", width, height));
+        }
+
+        (!parts.is_empty()).then(|| parts.join("  "))
+    }
+
+    pub fn summary(&self) -> String {
+        [self.camera(), self.exposure()]
+            .into_iter()
+            .flatten()
+            .collect::<Vec<String>>()
+            .join("\n")
+    }
+}
+
+/// "1/250 s" for fast shutter speeds, "2.5 s" for long exposures.
+fn shutter_speed(seconds: f64) -> String {
+    if seconds < 0.5 {
+        format!("1/{} s", (1.0 / seconds).round())
+    } else {
+        format!("{} s", trim_decimals(seconds))
+    }
+}
+
+/// One decimal at most, none when it would be zero.
+fn trim_decimals(value: f64) -> String {
+    let rounded = (value * 10.0).round() / 10.0;
+    if rounded.fract() == 0.0 {
+        format!("{}", rounded as i64)
+    } else {
+        format!("{:.1}", rounded)
+    }
+}
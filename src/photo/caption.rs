@@ -0,0 +1,428 @@
+use std::fs;
+use std::io::{BufReader, Read};
+use std::path::Path;
+
+use exif::{Exif, In, Tag, Value};
+
+/// XMP packets are stored in an APP1 segment starting with this namespace.
+const XMP_SIGNATURE: &[u8] = b"http://ns.adobe.com/xap/1.0/\0";
+/// IPTC records live in a Photoshop image resource inside APP13.
+const PHOTOSHOP_SIGNATURE: &[u8] = b"Photoshop 3.0\0";
+const IPTC_RESOURCE_ID: u16 = 0x0404;
+const IPTC_CAPTION_ABSTRACT: u8 = 120;
+const IPTC_OBJECT_NAME: u8 = 5;
+
+/// Descriptions some cameras write into every photo.
+const CAMERA_DEFAULTS: [&str; 5] = [
+    "OLYMPUS DIGITAL CAMERA",
+    "SONY DSC",
+    "DIGITAL CAMERA",
+    "Default",
+    "SAMSUNG",
+];
+
+/// Finds a caption for the photo. Sidecar files win over embedded metadata so
+/// captions can be curated without touching the photos:
+/// `photo.txt`/`photo.jpg.txt`, `photo.xmp`/`photo.jpg.xmp`, embedded XMP
+/// `dc:description` or `dc:title`, IPTC Caption-Abstract or Object Name and
+/// finally EXIF ImageDescription.
+pub fn read_caption(path: &Path, exif: Option<&Exif>) -> Option<String> {
+    read_sidecar(path, "txt")
+        .and_then(|text| clean(&text))
+        .or_else(|| read_sidecar(path, "xmp").and_then(|xmp| xmp_caption(&xmp)))
+        .or_else(|| jpeg_caption(path))
+        .or_else(|| exif.and_then(exif_caption))
+}
+
+fn read_sidecar(path: &Path, extension: &str) -> Option<String> {
+    let mut appended = path.as_os_str().to_owned();
+    appended.push(".");
+    appended.push(extension);
+
+    [path.with_extension(extension), appended.into()]
+        .iter()
+        .find_map(|sidecar| fs::read_to_string(sidecar).ok())
+}
+
+fn exif_caption(exif: &Exif) -> Option<String> {
+    match exif.get_field(Tag::ImageDescription, In::PRIMARY)?.value {
+        Value::Ascii(ref v) if !v.is_empty() => clean(&String::from_utf8_lossy(&v[0])),
+        _ => None,
+    }
+}
+
+/// Reads the XMP and IPTC captions from the segments before the image data.
+fn jpeg_caption(path: &Path) -> Option<String> {
+    let file = fs::File::open(path).ok()?;
+    let mut reader = BufReader::new(file);
+
+    let mut marker = [0u8; 2];
+    reader.read_exact(&mut marker).ok()?;
+    if marker != [0xFF, 0xD8] {
+        return None;
+    }
+
+    let mut xmp = None;
+    let mut iptc = None;
+    loop {
+        reader.read_exact(&mut marker).ok()?;
+        // Start of scan, the metadata is over
+        if marker[0] != 0xFF || marker[1] == 0xDA || marker[1] == 0xD9 {
+            break;
+        }
+
+        let mut length = [0u8; 2];
+        reader.read_exact(&mut length).ok()?;
+        let length = (u16::from_be_bytes(length) as usize).checked_sub(2)?;
+        let mut segment = vec![0u8; length];
+        reader.read_exact(&mut segment).ok()?;
+
+        match marker[1] {
+            0xE1 if segment.starts_with(XMP_SIGNATURE) => {
+                xmp = Some(String::from_utf8_lossy(&segment[XMP_SIGNATURE.len()..]).into_owned());
+            }
+            0xED if segment.starts_with(PHOTOSHOP_SIGNATURE) => {
+                iptc = iptc_caption(&segment[PHOTOSHOP_SIGNATURE.len()..]);
+            }
+            _ => {}
+        }
+    }
+
+    xmp.and_then(|xmp| xmp_caption(&xmp)).or(iptc)
+}
+
+/// Looks through the Photoshop image resources for the IPTC block.
+fn iptc_caption(mut resources: &[u8]) -> Option<String> {
+    while resources.len() >= 12 && resources.starts_with(b"8BIM") {
+        let id = u16::from_be_bytes([resources[4], resources[5]]);
+        // Pascal string name, padded to an even length
+        let name_length = resources[6] as usize;
+        let name_end = 6 + (name_length + 2) / 2 * 2;
+        let size_bytes = resources.get(name_end..name_end + 4)?;
+        let size = u32::from_be_bytes(size_bytes.try_into().ok()?) as usize;
+        let data = resources.get(name_end + 4..name_end + 4 + size)?;
+
+        if id == IPTC_RESOURCE_ID {
+            return iptc_records(data);
+        }
+        resources = resources.get(name_end + 4 + size + size % 2..)?;
+    }
+
+    None
+}
+
+fn iptc_records(mut data: &[u8]) -> Option<String> {
+    let mut title = None;
+    while data.len() >= 5 && data[0] == 0x1C {
+        let (record, dataset) = (data[1], data[2]);
+        let size = u16::from_be_bytes([data[3], data[4]]) as usize;
+        // A truncated record still leaves the title read before it
+        let Some(value) = data.get(5..5 + size) else {
+            break;
+        };
+
+        if record == 2 {
+            let text = String::from_utf8_lossy(value);
+            match dataset {
+                IPTC_CAPTION_ABSTRACT => {
+                    if let Some(caption) = clean(&text) {
+                        return Some(caption);
+                    }
+                }
+                IPTC_OBJECT_NAME => title = clean(&text),
+                _ => {}
+            }
+        }
+        data = &data[5 + size..];
+    }
+
+    title
+}
+
+/// Reads `dc:description`, falling back to `dc:title`, from an XMP packet.
+fn xmp_caption(xmp: &str) -> Option<String> {
+    ["dc:description", "dc:title"]
+        .iter()
+        .find_map(|property| xmp_property(xmp, property))
+}
+
+fn xmp_property(xmp: &str, property: &str) -> Option<String> {
+    // Attribute form: dc:description="..."
+    let attribute = format!("{}=\"", property);
+    if let Some(start) = xmp.find(&attribute) {
+        let value = &xmp[start + attribute.len()..];
+        let end = value.find('"')?;
+        return clean(&unescape(&value[..end]));
+    }
+
+    // Element form, usually an rdf:Alt with one rdf:li per language. The
+    // first entry is the default language.
+    let open = format!("<{}", property);
+    let close = format!("</{}>", property);
+    let start = xmp.find(&open)?;
+    let end = start + xmp[start..].find(&close)?;
+    let element = &xmp[start..end];
+
+    let li = element.find("<rdf:li")?;
+    let text_start = li + element[li..].find('>')? + 1;
+    let text_end = text_start + element[text_start..].find("</rdf:li>")?;
+    clean(&unescape(&element[text_start..text_end]))
+}
+
+fn unescape(text: &str) -> String {
+    text.replace("&lt;", "<")
+        .replace("&gt;", ">")
+        .replace("&quot;", "\"")
+        .replace("&apos;", "'")
+        .replace("&#xA;", "\n")
+        .replace("&amp;", "&")
+}
+
+/// Trims the caption and drops empty ones and camera defaults.
+fn clean(text: &str) -> Option<String> {
+    let text = text.trim_matches(|c: char| c.is_whitespace() || c == '\0');
+    if text.is_empty()
+        || CAMERA_DEFAULTS
+            .iter()
+            .any(|default| text.eq_ignore_ascii_case(default))
+    {
+        return None;
+    }
+
+    Some(text.to_string())
+}
+
+#[cfg(test)]
+mod tests {
+    use std::path::PathBuf;
+
+    use super::*;
+
+    fn iptc_record(dataset: u8, text: &str) -> Vec<u8> {
+        let mut record = vec![0x1C, 2, dataset];
+        record.extend((text.len() as u16).to_be_bytes());
+        record.extend(text.as_bytes());
+        record
+    }
+
+    /// A Photoshop image resource, with the name padded to an even length.
+    fn resource(id: u16, name: &str, data: &[u8]) -> Vec<u8> {
+        let mut resource = b"8BIM".to_vec();
+        resource.extend(id.to_be_bytes());
+        resource.push(name.len() as u8);
+        resource.extend(name.as_bytes());
+        if (name.len() + 1) % 2 == 1 {
+            resource.push(0);
+        }
+        resource.extend((data.len() as u32).to_be_bytes());
+        resource.extend(data);
+        if data.len() % 2 == 1 {
+            resource.push(0);
+        }
+        resource
+    }
+
+    fn segment(marker: u8, data: &[u8]) -> Vec<u8> {
+        let mut segment = vec![0xFF, marker];
+        segment.extend(((data.len() + 2) as u16).to_be_bytes());
+        segment.extend(data);
+        segment
+    }
+
+    fn jpeg(name: &str, segments: &[Vec<u8>]) -> PathBuf {
+        let mut bytes = vec![0xFF, 0xD8];
+        for segment in segments {
+            bytes.extend(segment);
+        }
+        bytes.extend([0xFF, 0xDA, 0x00, 0x02, 0xFF, 0xD9]);
+
+        let path = std::env::temp_dir().join(format!(
+            "pi-photo-frame-caption-{}-{}.jpg",
+            std::process::id(),
+            name
+        ));
+        fs::write(&path, bytes).unwrap();
+        path
+    }
+
+    fn app13(resources: &[u8]) -> Vec<u8> {
+        segment(0xED, &[PHOTOSHOP_SIGNATURE, resources].concat())
+    }
+
+    #[test]
+    fn reads_the_iptc_caption() {
+        let records = [
+            iptc_record(IPTC_OBJECT_NAME, "Title"),
+            iptc_record(IPTC_CAPTION_ABSTRACT, " Sunset at the beach \0"),
+        ]
+        .concat();
+        let resources = [
+            resource(0x03ED, "", &[0; 16]),
+            resource(IPTC_RESOURCE_ID, "odd", &records),
+        ]
+        .concat();
+        assert_eq!(
+            iptc_caption(&resources).as_deref(),
+            Some("Sunset at the beach")
+        );
+    }
+
+    #[test]
+    fn falls_back_to_the_iptc_object_name() {
+        let records = [
+            iptc_record(IPTC_OBJECT_NAME, "Beach"),
+            iptc_record(IPTC_CAPTION_ABSTRACT, "SONY DSC"),
+            iptc_record(25, "keyword"),
+        ]
+        .concat();
+        let resources = resource(IPTC_RESOURCE_ID, "", &records);
+        assert_eq!(iptc_caption(&resources).as_deref(), Some("Beach"));
+    }
+
+    #[test]
+    fn survives_truncated_iptc() {
+        let records = [
+            iptc_record(IPTC_OBJECT_NAME, "Beach"),
+            iptc_record(IPTC_CAPTION_ABSTRACT, "Sunset at the beach"),
+        ]
+        .concat();
+        let resources = resource(IPTC_RESOURCE_ID, "", &records);
+
+        for end in 0..resources.len() {
+            let caption = iptc_caption(&resources[..end]);
+            assert!(
+                caption.is_none() || caption.as_deref() == Some("Beach"),
+                "{} {:?}",
+                end,
+                caption
+            );
+        }
+        // The record is cut off, the resource and the title are whole
+        let cut = &records[..records.len() - 4];
+        assert_eq!(
+            iptc_caption(&resource(IPTC_RESOURCE_ID, "", cut)).as_deref(),
+            Some("Beach")
+        );
+    }
+
+    #[test]
+    fn ignores_malformed_iptc() {
+        assert_eq!(iptc_caption(b""), None);
+        assert_eq!(iptc_caption(b"8BIM"), None);
+        assert_eq!(iptc_caption(&[0xFF; 64]), None);
+        // A size far past the end
+        let mut resources = resource(IPTC_RESOURCE_ID, "", &iptc_record(120, "x"));
+        resources[8..12].copy_from_slice(&u32::MAX.to_be_bytes());
+        assert_eq!(iptc_caption(&resources), None);
+        // A name length past the end
+        assert_eq!(iptc_caption(b"8BIM\x04\x04\xFFabcde"), None);
+        // Not IPTC records
+        let resources = resource(IPTC_RESOURCE_ID, "", b"garbage");
+        assert_eq!(iptc_caption(&resources), None);
+    }
+
+    #[test]
+    fn reads_xmp_elements() {
+        let xmp = r#"<x:xmpmeta><rdf:RDF><rdf:Description>
+            <dc:title><rdf:Alt><rdf:li xml:lang="x-default">Title</rdf:li></rdf:Alt></dc:title>
+            <dc:description><rdf:Alt>
+                <rdf:li xml:lang="x-default">Fish &amp; chips&#xA;on the pier</rdf:li>
+                <rdf:li xml:lang="de">Fisch</rdf:li>
+            </rdf:Alt></dc:description>
+        </rdf:Description></rdf:RDF></x:xmpmeta>"#;
+        assert_eq!(
+            xmp_caption(xmp).as_deref(),
+            Some("Fish & chips\non the pier")
+        );
+    }
+
+    #[test]
+    fn reads_xmp_attributes() {
+        let xmp = r#"<rdf:Description dc:description="&quot;Hello&quot;" dc:title="Title"/>"#;
+        assert_eq!(xmp_caption(xmp).as_deref(), Some("\"Hello\""));
+    }
+
+    #[test]
+    fn falls_back_to_the_xmp_title() {
+        let xmp = r#"<dc:description><rdf:Alt><rdf:li xml:lang="x-default">DIGITAL CAMERA</rdf:li></rdf:Alt></dc:description>
+            <dc:title><rdf:Alt><rdf:li>Title</rdf:li></rdf:Alt></dc:title>"#;
+        assert_eq!(xmp_caption(xmp).as_deref(), Some("Title"));
+    }
+
+    #[test]
+    fn ignores_broken_xmp() {
+        assert_eq!(xmp_caption(""), None);
+        assert_eq!(xmp_caption("<dc:description><rdf:Alt><rdf:li>open"), None);
+        assert_eq!(
+            xmp_caption("<dc:description>no list</dc:description>"),
+            None
+        );
+        assert_eq!(xmp_caption(r#"dc:description="unterminated"#), None);
+    }
+
+    #[test]
+    fn reads_captions_from_jpeg_segments() {
+        let xmp = [
+            XMP_SIGNATURE,
+            br#"<dc:description><rdf:Alt><rdf:li>From XMP</rdf:li></rdf:Alt></dc:description>"#,
+        ]
+        .concat();
+        let iptc = resource(
+            IPTC_RESOURCE_ID,
+            "",
+            &iptc_record(IPTC_CAPTION_ABSTRACT, "From IPTC"),
+        );
+
+        // XMP wins over IPTC
+        let path = jpeg("both", &[app13(&iptc), segment(0xE1, &xmp)]);
+        assert_eq!(jpeg_caption(&path).as_deref(), Some("From XMP"));
+        fs::remove_file(path).unwrap();
+
+        let path = jpeg("iptc", &[segment(0xE0, b"JFIF\0"), app13(&iptc)]);
+        assert_eq!(jpeg_caption(&path).as_deref(), Some("From IPTC"));
+        fs::remove_file(path).unwrap();
+    }
+
+    #[test]
+    fn survives_truncated_jpegs() {
+        let iptc = resource(
+            IPTC_RESOURCE_ID,
+            "",
+            &iptc_record(IPTC_CAPTION_ABSTRACT, "From IPTC"),
+        );
+        let path = jpeg("truncated", &[app13(&iptc)]);
+        let bytes = fs::read(&path).unwrap();
+        for end in [0, 1, 2, 3, 5, 20, bytes.len() - 6] {
+            fs::write(&path, &bytes[..end]).unwrap();
+            // Gives up rather than reading past the end
+            let caption = jpeg_caption(&path);
+            assert!(caption.is_none(), "{} {:?}", end, caption);
+        }
+
+        // A segment length below the two length bytes
+        fs::write(&path, [0xFF, 0xD8, 0xFF, 0xED, 0x00, 0x01]).unwrap();
+        assert_eq!(jpeg_caption(&path), None);
+        fs::remove_file(path).unwrap();
+    }
+
+    #[test]
+    fn prefers_sidecars() {
+        let path = jpeg("sidecar", &[]);
+        let sidecar = path.with_extension("txt");
+        fs::write(&sidecar, "  From the sidecar\n").unwrap();
+        assert_eq!(
+            read_caption(&path, None).as_deref(),
+            Some("From the sidecar")
+        );
+        fs::remove_file(sidecar).unwrap();
+        fs::remove_file(path).unwrap();
+    }
+
+    #[test]
+    fn drops_camera_defaults() {
+        assert_eq!(clean("  OLYMPUS DIGITAL CAMERA  "), None);
+        assert_eq!(clean("sony dsc"), None);
+        assert_eq!(clean("\0\0"), None);
+        assert_eq!(clean(" Holiday \0").as_deref(), Some("Holiday"));
+    }
+}
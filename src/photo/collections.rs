@@ -0,0 +1,70 @@
+use std::fs;
+use std::io::Error;
+use std::path::Path;
+
+use serde::{Deserialize, Serialize};
+use tracing::warn;
+
+/// Photos marked over MQTT, kept across restarts.
+#[derive(Deserialize, Serialize, Default, Debug, Clone)]
+pub struct Collections {
+    #[serde(default)]
+    pub favorites: Vec<String>,
+    /// Never shown again
+    #[serde(default)]
+    pub hidden: Vec<String>,
+}
+
+impl Collections {
+    pub fn is_favorite(&self, path: &Path) -> bool {
+        let names = names(path);
+        self.favorites.iter().any(|entry| names.contains(entry))
+    }
+
+    pub fn is_hidden(&self, path: &Path) -> bool {
+        let names = names(path);
+        self.hidden.iter().any(|entry| names.contains(entry))
+    }
+}
+
+/// Takes the photo out of `entries`. Returns whether it was in there.
+pub fn remove_entry(entries: &mut Vec<String>, path: &Path) -> bool {
+    let names = names(path);
+    let count = entries.len();
+    entries.retain(|entry| !names.contains(entry));
+    entries.len() != count
+}
+
+/// Paths are stored canonical, so that a photo matches however its folder
+/// was configured. Older entries were stored as given.
+fn names(path: &Path) -> Vec<String> {
+    [Some(path.to_path_buf()), path.canonicalize().ok()]
+        .into_iter()
+        .flatten()
+        .filter_map(|path| path.to_str().map(|path| path.to_string()))
+        .collect()
+}
+
+fn collections_path() -> &'static Path {
+    let path = Path::new(".collections.json5");
+    if path.exists() {
+        path
+    } else {
+        Path::new("/var/lib/pi-photo-frame/collections.json5")
+    }
+}
+
+pub fn load_collections() -> Collections {
+    let path = collections_path();
+    match fs::read_to_string(path) {
+        Ok(text) => json5::from_str(&text).unwrap_or_else(|e| {
+            warn!("Invalid collections file {:?}, {}", path, e);
+            Collections::default()
+        }),
+        Err(_) => Collections::default(),
+    }
+}
+
+pub fn write_collections(collections: &Collections) -> Result<(), Error> {
+    fs::write(collections_path(), json5::to_string(collections).unwrap())
+}
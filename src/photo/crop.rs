@@ -0,0 +1,58 @@
+use gtk::gdk_pixbuf::Pixbuf;
+use serde::Deserialize;
+
+use super::saliency::{best_window, EnergyMap};
+
+/// Longest side of the copy used to find the crop window.
+const ENERGY_MAP_SIZE: i32 = 160;
+
+#[derive(Deserialize, Default, Debug, Clone, Copy, PartialEq)]
+#[serde(rename_all = "lowercase")]
+pub enum FitMode {
+    /// Show the whole photo, with a background around it
+    #[default]
+    Fit,
+    /// Crop the photo to the screen aspect ratio
+    Fill,
+}
+
+/// Crops the photo to the screen aspect ratio, keeping the part with the most
+/// detail. Returns `None` when no crop is needed or when more than
+/// `max_crop` (0.0 - 1.0) of the photo would be cut off.
+pub fn smart_crop(pixbuf: &Pixbuf, screen_size: (i32, i32), max_crop: f64) -> Option<Pixbuf> {
+    let (screen_width, screen_height) = screen_size;
+    if screen_width <= 0 || screen_height <= 0 {
+        return None;
+    }
+
+    let (width, height) = (pixbuf.width(), pixbuf.height());
+    let screen_aspect = screen_width as f64 / screen_height as f64;
+    let horizontal = width as f64 / height as f64 > screen_aspect;
+    let (crop_width, crop_height) = if horizontal {
+        ((height as f64 * screen_aspect).round() as i32, height)
+    } else {
+        (width, (width as f64 / screen_aspect).round() as i32)
+    };
+
+    let cropped = 1.0 - (crop_width as f64 * crop_height as f64) / (width as f64 * height as f64);
+    if crop_width <= 0 || crop_height <= 0 || cropped <= 0.0 || cropped > max_crop {
+        return None;
+    }
+
+    let map = EnergyMap::from_pixbuf(pixbuf, ENERGY_MAP_SIZE)?;
+    let profile = map.profile(horizontal);
+    let (length, crop_length) = if horizontal {
+        (width, crop_width)
+    } else {
+        (height, crop_height)
+    };
+    let window = (crop_length as f64 / length as f64 * profile.len() as f64).round() as usize;
+    let start = best_window(&profile, window) as f64 / profile.len() as f64;
+    let offset = ((start * length as f64) as i32).clamp(0, length - crop_length);
+
+    Some(if horizontal {
+        pixbuf.new_subpixbuf(offset, 0, crop_width, crop_height)
+    } else {
+        pixbuf.new_subpixbuf(0, offset, crop_width, crop_height)
+    })
+}
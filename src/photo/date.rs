@@ -0,0 +1,381 @@
+use std::path::Path;
+
+use exif::{Exif, In, Tag, Value};
+use gtk::glib::{DateTime, TimeZone};
+use serde::Deserialize;
+
+#[derive(Deserialize, Default, Debug, Clone, Copy, PartialEq)]
+#[serde(rename_all = "lowercase")]
+pub enum PhotoDateStyle {
+    /// `photo_date_format`, using the `g_date_time_format` syntax
+    #[default]
+    Pattern,
+    /// "3 years ago, summer 2021"
+    Relative,
+}
+
+/// When a photo was taken, as recorded by the camera.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct CaptureDate {
+    pub year: i32,
+    pub month: u8,
+    pub day: u8,
+    pub hour: u8,
+    pub minute: u8,
+    pub second: u8,
+    /// UTC offset in minutes, when the camera recorded one
+    pub offset: Option<i16>,
+}
+
+impl CaptureDate {
+    /// Reads `DateTimeOriginal`, falling back to `DateTime`, with the time
+    /// zone from `OffsetTimeOriginal` when present.
+    pub fn from_exif(exif: &Exif) -> Option<Self> {
+        let ascii = |tag: Tag| match exif.get_field(tag, In::PRIMARY) {
+            Some(field) => match field.value {
+                Value::Ascii(ref v) if !v.is_empty() => Some(v[0].clone()),
+                _ => None,
+            },
+            None => None,
+        };
+
+        let (mut date_time, original) =
+            match ascii(Tag::DateTimeOriginal).and_then(|v| exif::DateTime::from_ascii(&v).ok()) {
+                Some(date_time) => (date_time, true),
+                None => (
+                    exif::DateTime::from_ascii(&ascii(Tag::DateTime)?).ok()?,
+                    false,
+                ),
+            };
+
+        if original {
+            if let Some(offset) = ascii(Tag::OffsetTimeOriginal) {
+                // A broken offset only loses the time zone
+                let _ = date_time.parse_offset(&offset);
+            }
+        }
+
+        CaptureDate {
+            year: date_time.year as i32,
+            month: date_time.month,
+            day: date_time.day,
+            hour: date_time.hour,
+            minute: date_time.minute,
+            second: date_time.second,
+            offset: date_time.offset,
+        }
+        .validated()
+    }
+
+    /// Finds a date in the file name or in the names of the two folders above
+    /// it, e.g. `IMG_20210704_153012.jpg` or `2021-07-04 Beach/`.
+    pub fn from_path(path: &Path) -> Option<Self> {
+        let mut names = vec![path.file_stem()];
+        let mut parent = path.parent();
+        for _ in 0..2 {
+            names.push(parent.and_then(|p| p.file_name()));
+            parent = parent.and_then(|p| p.parent());
+        }
+
+        names
+            .into_iter()
+            .flatten()
+            .filter_map(|name| name.to_str())
+            .find_map(CaptureDate::from_name)
+    }
+
+    fn from_name(name: &str) -> Option<Self> {
+        // Runs of digits, e.g. "IMG_20210704_153012" -> ["20210704", "153012"]
+        let runs: Vec<&str> = name
+            .split(|c: char| !c.is_ascii_digit())
+            .filter(|run| !run.is_empty())
+            .collect();
+
+        for (i, run) in runs.iter().enumerate() {
+            let (date, next) = if run.len() == 8 {
+                (
+                    (&run[0..4], &run[4..6], &run[6..8]),
+                    runs.get(i + 1).copied(),
+                )
+            } else if run.len() == 4 && i + 2 < runs.len() {
+                // "2021-07-04", "2021_07_04"
+                ((*run, runs[i + 1], runs[i + 2]), runs.get(i + 3).copied())
+            } else {
+                continue;
+            };
+
+            let time = next
+                .filter(|t| t.len() == 6)
+                .map(|t| (&t[0..2], &t[2..4], &t[4..6]));
+            if let Some(parsed) = CaptureDate::from_parts(date, time) {
+                return Some(parsed);
+            }
+        }
+
+        None
+    }
+
+    fn from_parts(date: (&str, &str, &str), time: Option<(&str, &str, &str)>) -> Option<Self> {
+        let number = |s: &str| s.parse::<u8>().ok();
+        let (hour, minute, second) = match time {
+            Some((h, m, s)) => (number(h)?, number(m)?, number(s)?),
+            None => (0, 0, 0),
+        };
+
+        CaptureDate {
+            year: date.0.parse().ok()?,
+            month: number(date.1)?,
+            day: number(date.2)?,
+            hour,
+            minute,
+            second,
+            offset: None,
+        }
+        .validated()
+    }
+
+    fn validated(self) -> Option<Self> {
+        let valid = (1900..=2100).contains(&self.year)
+            && (1..=12).contains(&self.month)
+            && (1..=31).contains(&self.day)
+            && self.hour < 24
+            && self.minute < 60
+            && self.second < 61;
+        valid.then_some(self)
+    }
+
+    /// The date in the time zone it was taken in, or the local time zone when
+    /// unknown.
+    pub fn to_date_time(self) -> Option<DateTime> {
+        let timezone = match self.offset {
+            Some(offset) => TimeZone::from_offset(offset as i32 * 60),
+            None => TimeZone::local(),
+        };
+
+        DateTime::new(
+            &timezone,
+            self.year,
+            self.month as i32,
+            self.day as i32,
+            self.hour as i32,
+            self.minute as i32,
+            self.second as f64,
+        )
+        .ok()
+    }
+
+    /// Seconds since the epoch.
+    pub fn timestamp(&self) -> Option<i64> {
+        self.to_date_time().map(|d| d.to_unix())
+    }
+
+    pub fn same_day(&self, other: &CaptureDate) -> bool {
+        (self.year, self.month, self.day) == (other.year, other.month, other.day)
+    }
+
+    pub fn format(&self, style: PhotoDateStyle, pattern: &str, southern: bool) -> Option<String> {
+        let date_time = self.to_date_time()?;
+        match style {
+            PhotoDateStyle::Pattern => date_time.format(pattern).ok().map(|s| s.to_string()),
+            PhotoDateStyle::Relative => {
+                let now = DateTime::now_local().ok()?;
+                Some(format!(
+                    "{}, {} {}",
+                    relative_age(&date_time, &now),
+                    self.season(southern),
+                    self.year
+                ))
+            }
+        }
+    }
+
+    fn season(&self, southern: bool) -> &'static str {
+        let seasons = ["winter", "spring", "summer", "autumn"];
+        // December belongs to the winter of its year
+        let index = (self.month as usize % 12) / 3;
+        if southern {
+            seasons[(index + 2) % 4]
+        } else {
+            seasons[index]
+        }
+    }
+}
+
+fn relative_age(then: &DateTime, now: &DateTime) -> String {
+    let days = (now.to_unix() - then.to_unix()).div_euclid(86400);
+    let plural = |n: i64, unit: &str| {
+        if n == 1 {
+            format!("1 {} ago", unit)
+        } else {
+            format!("{} {}s ago", n, unit)
+        }
+    };
+
+    match days {
+        i64::MIN..=0 => "today".to_string(),
+        1 => "yesterday".to_string(),
+        2..=6 => plural(days, "day"),
+        7..=29 => plural(days / 7, "week"),
+        30..=364 => plural(days / 30, "month"),
+        _ => plural(days / 365, "year"),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn date(year: i32, month: u8, day: u8) -> CaptureDate {
+        CaptureDate {
+            year,
+            month,
+            day,
+            hour: 0,
+            minute: 0,
+            second: 0,
+            offset: None,
+        }
+    }
+
+    fn date_time(year: i32, month: u8, day: u8, hour: u8, minute: u8, second: u8) -> CaptureDate {
+        CaptureDate {
+            hour,
+            minute,
+            second,
+            ..date(year, month, day)
+        }
+    }
+
+    #[test]
+    fn reads_dates_from_file_names() {
+        let cases = [
+            ("IMG_20210704_153012.jpg", date_time(2021, 7, 4, 15, 30, 12)),
+            ("PXL_20230101_000001123.jpg", date(2023, 1, 1)),
+            ("20190512.jpg", date(2019, 5, 12)),
+            ("2021-07-04 12.00.00.jpg", date(2021, 7, 4)),
+            ("Screenshot_2020_02_29-x.png", date(2020, 2, 29)),
+        ];
+        for (name, expected) in cases {
+            assert_eq!(
+                CaptureDate::from_path(Path::new(name)),
+                Some(expected),
+                "{}",
+                name
+            );
+        }
+    }
+
+    #[test]
+    fn reads_dates_from_folder_names() {
+        assert_eq!(
+            CaptureDate::from_path(Path::new("/photos/2021-07-04 Beach/IMG_1234.jpg")),
+            Some(date(2021, 7, 4))
+        );
+        assert_eq!(
+            CaptureDate::from_path(Path::new("/photos/2018_12_24 Xmas/day 1/DSC0042.jpg")),
+            Some(date(2018, 12, 24))
+        );
+        // The file name wins over the folder
+        assert_eq!(
+            CaptureDate::from_path(Path::new("/2021-07-04/IMG_20210705_080000.jpg")),
+            Some(date_time(2021, 7, 5, 8, 0, 0))
+        );
+        // Only two folders up
+        assert_eq!(
+            CaptureDate::from_path(Path::new("/2021-07-04/a/b/IMG_1234.jpg")),
+            None
+        );
+    }
+
+    #[test]
+    fn ignores_numbers_that_are_not_dates() {
+        let names = [
+            "IMG_1234.jpg",
+            "DSC01234.jpg",
+            "IMG_20211304.jpg",
+            "IMG_20210732.jpg",
+            "18000101.jpg",
+            "1234-56-78.jpg",
+            "photo.jpg",
+        ];
+        for name in names {
+            assert_eq!(CaptureDate::from_path(Path::new(name)), None, "{}", name);
+        }
+    }
+
+    #[test]
+    fn skips_an_invalid_time() {
+        assert_eq!(
+            CaptureDate::from_path(Path::new("IMG_20210704_256000.jpg")),
+            None
+        );
+        // Not six digits, so not a time
+        assert_eq!(
+            CaptureDate::from_path(Path::new("IMG_20210704_1.jpg")),
+            Some(date(2021, 7, 4))
+        );
+    }
+
+    #[test]
+    fn uses_the_recorded_time_zone() {
+        let taken = CaptureDate {
+            offset: Some(120),
+            ..date_time(2021, 7, 4, 15, 30, 12)
+        };
+        assert_eq!(taken.timestamp(), Some(1_625_405_412));
+        assert_eq!(
+            taken
+                .format(PhotoDateStyle::Pattern, "%Y-%m-%d %H:%M", false)
+                .as_deref(),
+            Some("2021-07-04 15:30")
+        );
+    }
+
+    #[test]
+    fn same_day_ignores_the_time() {
+        assert!(date_time(2021, 7, 4, 1, 0, 0).same_day(&date_time(2021, 7, 4, 23, 0, 0)));
+        assert!(!date(2021, 7, 4).same_day(&date(2020, 7, 4)));
+    }
+
+    #[test]
+    fn seasons() {
+        let seasons: Vec<&str> = (1..=12)
+            .map(|month| date(2021, month, 1).season(false))
+            .collect();
+        assert_eq!(
+            seasons,
+            [
+                "winter", "winter", "spring", "spring", "spring", "summer", "summer", "summer",
+                "autumn", "autumn", "autumn", "winter"
+            ]
+        );
+
+        assert_eq!(date(2021, 1, 1).season(true), "summer");
+        assert_eq!(date(2021, 4, 1).season(true), "autumn");
+        assert_eq!(date(2021, 7, 1).season(true), "winter");
+        assert_eq!(date(2021, 10, 1).season(true), "spring");
+    }
+
+    #[test]
+    fn relative_ages() {
+        let now = DateTime::from_unix_utc(1_700_000_000).unwrap();
+        let days_ago = |days: i64| {
+            let then = DateTime::from_unix_utc(1_700_000_000 - days * 86400 - 60).unwrap();
+            relative_age(&then, &now)
+        };
+
+        assert_eq!(days_ago(0), "today");
+        assert_eq!(days_ago(1), "yesterday");
+        assert_eq!(days_ago(3), "3 days ago");
+        assert_eq!(days_ago(7), "1 week ago");
+        assert_eq!(days_ago(20), "2 weeks ago");
+        assert_eq!(days_ago(45), "1 month ago");
+        assert_eq!(days_ago(300), "10 months ago");
+        assert_eq!(days_ago(365), "1 year ago");
+        assert_eq!(days_ago(3 * 365 + 10), "3 years ago");
+
+        // Clocks that are off don't make photos from the future
+        let later = DateTime::from_unix_utc(1_700_086_400).unwrap();
+        assert_eq!(relative_age(&later, &now), "today");
+    }
+}
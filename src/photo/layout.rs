@@ -0,0 +1,344 @@
+use serde::Deserialize;
+
+#[derive(Deserialize, Default, Debug, Clone, Copy, PartialEq)]
+#[serde(rename_all = "lowercase")]
+pub enum CollageLayout {
+    /// Equal cells, each photo fitted inside its cell
+    Grid,
+    /// Rows of photos scaled to the same height, like a photo album page
+    #[default]
+    Mosaic,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Rect {
+    pub x: i32,
+    pub y: i32,
+    pub width: i32,
+    pub height: i32,
+}
+
+/// Places photos with the given aspect ratios (width / height) on a screen of
+/// `width` x `height`, keeping the aspect ratio of every photo. Returns one
+/// rectangle per photo, in the same order.
+pub fn collage_layout(
+    layout: CollageLayout,
+    aspects: &[f64],
+    width: i32,
+    height: i32,
+    spacing: i32,
+) -> Vec<Rect> {
+    if aspects.is_empty() || width <= 0 || height <= 0 {
+        return vec![];
+    }
+
+    match layout {
+        CollageLayout::Grid => grid_layout(aspects, width as f64, height as f64, spacing as f64),
+        CollageLayout::Mosaic => {
+            mosaic_layout(aspects, width as f64, height as f64, spacing as f64)
+        }
+    }
+}
+
+/// Tries every column count and keeps the one that covers the most of the
+/// screen.
+fn grid_layout(aspects: &[f64], width: f64, height: f64, spacing: f64) -> Vec<Rect> {
+    let n = aspects.len();
+    let fit = |aspect: f64, cell_width: f64, cell_height: f64| {
+        if aspect > cell_width / cell_height {
+            (cell_width, cell_width / aspect)
+        } else {
+            (cell_height * aspect, cell_height)
+        }
+    };
+
+    let mut best: Option<(f64, usize)> = None;
+    for cols in 1..=n {
+        let rows = n.div_ceil(cols);
+        let cell_width = (width - spacing * (cols - 1) as f64) / cols as f64;
+        let cell_height = (height - spacing * (rows - 1) as f64) / rows as f64;
+        if cell_width <= 0.0 || cell_height <= 0.0 {
+            continue;
+        }
+
+        let covered: f64 = aspects
+            .iter()
+            .map(|a| {
+                let (w, h) = fit(*a, cell_width, cell_height);
+                w * h
+            })
+            .sum();
+        if best.is_none_or(|(c, _)| covered > c) {
+            best = Some((covered, cols));
+        }
+    }
+
+    let Some((_, cols)) = best else {
+        return vec![];
+    };
+    let rows = n.div_ceil(cols);
+    let cell_width = (width - spacing * (cols - 1) as f64) / cols as f64;
+    let cell_height = (height - spacing * (rows - 1) as f64) / rows as f64;
+
+    aspects
+        .iter()
+        .enumerate()
+        .map(|(i, aspect)| {
+            let (row, col) = (i / cols, i % cols);
+            // Center the last row when it isn't full
+            let in_row = if row == rows - 1 {
+                n - row * cols
+            } else {
+                cols
+            };
+            let row_offset = (cols - in_row) as f64 * (cell_width + spacing) / 2.0;
+
+            let (w, h) = fit(*aspect, cell_width, cell_height);
+            let cell_x = row_offset + col as f64 * (cell_width + spacing);
+            let cell_y = row as f64 * (cell_height + spacing);
+            to_rect(
+                cell_x + (cell_width - w) / 2.0,
+                cell_y + (cell_height - h) / 2.0,
+                w,
+                h,
+            )
+        })
+        .collect()
+}
+
+/// Justified rows: tries every way of splitting the photos into consecutive
+/// rows and keeps the one that covers the most of the screen. Every row
+/// spans the full width, then the whole block is scaled down if it is too
+/// tall.
+fn mosaic_layout(aspects: &[f64], width: f64, height: f64, spacing: f64) -> Vec<Rect> {
+    let n = aspects.len();
+
+    let mut best: Option<(f64, Vec<usize>)> = None;
+    // Each bit of `breaks` decides whether a new row starts after photo i
+    for breaks in 0..(1u32 << (n - 1)) {
+        let mut rows = vec![];
+        let mut start = 0;
+        for i in 0..n {
+            if i == n - 1 || breaks & (1 << i) != 0 {
+                rows.push(i + 1 - start);
+                start = i + 1;
+            }
+        }
+
+        let (heights, scale) = mosaic_rows(aspects, &rows, width, height, spacing);
+        let covered: f64 = rows
+            .iter()
+            .zip(heights.iter())
+            .scan(0, |start, (count, row_height)| {
+                let sum: f64 = aspects[*start..*start + count].iter().sum();
+                *start += count;
+                Some(sum * (row_height * scale).powi(2))
+            })
+            .sum();
+
+        if best.as_ref().is_none_or(|(c, _)| covered > *c) {
+            best = Some((covered, rows));
+        }
+    }
+
+    let Some((_, rows)) = best else {
+        return vec![];
+    };
+    let (heights, scale) = mosaic_rows(aspects, &rows, width, height, spacing);
+
+    let total_height: f64 =
+        heights.iter().map(|h| h * scale).sum::<f64>() + spacing * (rows.len() - 1) as f64;
+    let mut y = (height - total_height) / 2.0;
+    let mut rects = Vec::with_capacity(n);
+    let mut start = 0;
+    for (count, row_height) in rows.iter().zip(heights.iter()) {
+        let row_height = row_height * scale;
+        let row_aspects = &aspects[start..start + count];
+        let row_width = row_aspects.iter().sum::<f64>() * row_height + spacing * (count - 1) as f64;
+
+        let mut x = (width - row_width) / 2.0;
+        for aspect in row_aspects {
+            let w = aspect * row_height;
+            rects.push(to_rect(x, y, w, row_height));
+            x += w + spacing;
+        }
+
+        y += row_height + spacing;
+        start += count;
+    }
+
+    rects
+}
+
+/// Returns the full-width height of each row and the factor needed to fit
+/// all rows on the screen.
+fn mosaic_rows(
+    aspects: &[f64],
+    rows: &[usize],
+    width: f64,
+    height: f64,
+    spacing: f64,
+) -> (Vec<f64>, f64) {
+    let mut heights = Vec::with_capacity(rows.len());
+    let mut start = 0;
+    for count in rows {
+        let sum: f64 = aspects[start..start + count].iter().sum();
+        heights.push((width - spacing * (count - 1) as f64) / sum);
+        start += count;
+    }
+
+    let available = height - spacing * (rows.len() - 1) as f64;
+    let total: f64 = heights.iter().sum();
+    let scale = if total > available {
+        available / total
+    } else {
+        1.0
+    };
+
+    (heights, scale)
+}
+
+fn to_rect(x: f64, y: f64, width: f64, height: f64) -> Rect {
+    Rect {
+        x: x.round() as i32,
+        y: y.round() as i32,
+        width: (width.round() as i32).max(1),
+        height: (height.round() as i32).max(1),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const WIDTH: i32 = 1920;
+    const HEIGHT: i32 = 1080;
+    const SPACING: i32 = 8;
+    /// Landscape, portrait, square and panorama photos
+    const ASPECTS: [f64; 6] = [1.5, 0.75, 1.0, 1.333, 2.5, 0.667];
+
+    fn layouts() -> Vec<(CollageLayout, Vec<f64>, Vec<Rect>)> {
+        let mut layouts = vec![];
+        for layout in [CollageLayout::Grid, CollageLayout::Mosaic] {
+            for count in 3..=ASPECTS.len() {
+                for offset in 0..ASPECTS.len() {
+                    let aspects: Vec<f64> = ASPECTS
+                        .iter()
+                        .cycle()
+                        .skip(offset)
+                        .take(count)
+                        .copied()
+                        .collect();
+                    let rects = collage_layout(layout, &aspects, WIDTH, HEIGHT, SPACING);
+                    layouts.push((layout, aspects, rects));
+                }
+            }
+        }
+        layouts
+    }
+
+    fn gap(a: &Rect, b: &Rect) -> i32 {
+        let horizontal = (b.x - (a.x + a.width)).max(a.x - (b.x + b.width));
+        let vertical = (b.y - (a.y + a.height)).max(a.y - (b.y + b.height));
+        horizontal.max(vertical)
+    }
+
+    #[test]
+    fn places_every_photo_on_the_screen() {
+        for (layout, aspects, rects) in layouts() {
+            assert_eq!(rects.len(), aspects.len(), "{:?} {:?}", layout, aspects);
+            for rect in rects {
+                assert!(rect.x >= 0 && rect.y >= 0, "{:?} {:?}", layout, rect);
+                assert!(rect.x + rect.width <= WIDTH, "{:?} {:?}", layout, rect);
+                assert!(rect.y + rect.height <= HEIGHT, "{:?} {:?}", layout, rect);
+            }
+        }
+    }
+
+    #[test]
+    fn keeps_the_aspect_ratios() {
+        for (layout, aspects, rects) in layouts() {
+            for (aspect, rect) in aspects.iter().zip(rects.iter()) {
+                let actual = rect.width as f64 / rect.height as f64;
+                assert!(
+                    (actual - aspect).abs() / aspect < 0.02,
+                    "{:?} {} {:?}",
+                    layout,
+                    aspect,
+                    rect
+                );
+            }
+        }
+    }
+
+    #[test]
+    fn never_overlaps_and_keeps_the_spacing() {
+        for (layout, aspects, rects) in layouts() {
+            for (i, a) in rects.iter().enumerate() {
+                for b in &rects[i + 1..] {
+                    // Rounding can take a pixel off the spacing
+                    assert!(
+                        gap(a, b) >= SPACING - 1,
+                        "{:?} {:?} {:?} {:?}",
+                        layout,
+                        aspects,
+                        a,
+                        b
+                    );
+                }
+            }
+        }
+    }
+
+    #[test]
+    fn fills_the_screen() {
+        for (layout, aspects, rects) in layouts() {
+            let covered: i32 = rects.iter().map(|r| r.width * r.height).sum();
+            assert!(
+                covered as f64 > 0.45 * (WIDTH * HEIGHT) as f64,
+                "{:?} {:?} covers {}",
+                layout,
+                aspects,
+                covered
+            );
+            if layout == CollageLayout::Grid {
+                continue;
+            }
+
+            // Mosaic rows reach the sides, or the rows the top and bottom
+            let left = rects.iter().map(|r| r.x).min().unwrap();
+            let right = rects.iter().map(|r| r.x + r.width).max().unwrap();
+            let top = rects.iter().map(|r| r.y).min().unwrap();
+            let bottom = rects.iter().map(|r| r.y + r.height).max().unwrap();
+            assert!(
+                (left <= 1 && right >= WIDTH - 1) || (top <= 1 && bottom >= HEIGHT - 1),
+                "{:?} {:?}",
+                aspects,
+                rects
+            );
+        }
+    }
+
+    #[test]
+    fn mosaic_rows_span_the_width() {
+        // Panoramas fit in rows without being scaled down to the height
+        let rects = collage_layout(CollageLayout::Mosaic, &[2.5; 4], WIDTH, HEIGHT, SPACING);
+        let rows: Vec<&Rect> = rects.iter().filter(|r| r.x <= 1).collect();
+        assert_eq!(rows.len(), 2);
+        for row in rows {
+            let right = rects
+                .iter()
+                .filter(|r| r.y == row.y)
+                .map(|r| r.x + r.width)
+                .max()
+                .unwrap();
+            assert!((right - WIDTH).abs() <= 1, "{:?}", rects);
+        }
+    }
+
+    #[test]
+    fn nothing_to_lay_out() {
+        assert!(collage_layout(CollageLayout::Mosaic, &[], WIDTH, HEIGHT, SPACING).is_empty());
+        assert!(collage_layout(CollageLayout::Grid, &[1.5; 3], 0, HEIGHT, SPACING).is_empty());
+    }
+}
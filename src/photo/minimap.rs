@@ -0,0 +1,279 @@
+use std::cell::{Cell, RefCell};
+use std::collections::HashMap;
+use std::f64::consts::PI;
+use std::fs;
+use std::path::{Path, PathBuf};
+use std::time::{Duration, Instant};
+
+use gtk::gdk_pixbuf::{Colorspace, InterpType, Pixbuf};
+use tracing::{debug, warn};
+
+use super::background::rgb_pixels;
+use super::provider::Config;
+use crate::gui::overlay::OverlayWidget;
+
+const TILE_SIZE: i32 = 256;
+const MAX_ZOOM: u8 = 19;
+/// Web Mercator stops at these latitudes
+const MAX_LATITUDE: f64 = 85.051_128;
+/// Shown where no tile is available
+const EMPTY_COLOR: [u8; 3] = [0xe0, 0xdc, 0xd4];
+const PIN_COLOR: [u8; 3] = [0xe5, 0x39, 0x35];
+const PIN_RADIUS: f64 = 7.0;
+const PIN_BORDER: f64 = 2.0;
+/// How long a tile that failed to download, or a server that didn't answer,
+/// is left alone, so that every photo nearby doesn't wait for it again.
+const DOWNLOAD_RETRY: Duration = Duration::from_secs(15 * 60);
+
+/// Slippy-map tiles laid out as `{z}/{x}/{y}.png` (or `.jpg`) in the offline
+/// tile directory or in the cache directory. Missing tiles are downloaded
+/// into the cache only when a tile URL is configured, and not asked for again
+/// for a while after a download fails.
+pub struct TileSource {
+    dirs: Vec<PathBuf>,
+    cache_dir: PathBuf,
+    url: Option<String>,
+    client: Option<reqwest::blocking::Client>,
+    /// When each tile that failed to download may be tried again
+    failed: RefCell<HashMap<(u8, u32, u32), Instant>>,
+    /// Set while the tile server can't be reached at all
+    unreachable_until: Cell<Option<Instant>>,
+}
+
+impl TileSource {
+    /// Returns `None` when the map overlay is not enabled.
+    pub fn new(config: &Config) -> Option<Self> {
+        if !config
+            .overlays
+            .iter()
+            .any(|overlay| overlay.widget == OverlayWidget::Map)
+        {
+            return None;
+        }
+
+        let cache_dir = PathBuf::from(&config.minimap_cache_dir);
+        let mut dirs: Vec<PathBuf> = config.minimap_tile_dir.iter().map(PathBuf::from).collect();
+        dirs.push(cache_dir.clone());
+
+        let client = config.minimap_tile_url.as_ref().and_then(|_| {
+            reqwest::blocking::Client::builder()
+                .user_agent(concat!("pi-photo-frame/", env!("CARGO_PKG_VERSION")))
+                .timeout(Duration::from_secs(10))
+                .build()
+                .map_err(|e| warn!("Failed to create tile client {}", e))
+                .ok()
+        });
+
+        Some(TileSource {
+            dirs,
+            cache_dir,
+            url: config.minimap_tile_url.clone(),
+            client,
+            failed: RefCell::new(HashMap::new()),
+            unreachable_until: Cell::new(None),
+        })
+    }
+
+    fn tile(&self, z: u8, x: u32, y: u32) -> Option<Pixbuf> {
+        let relative = Path::new(&z.to_string()).join(x.to_string());
+        for dir in &self.dirs {
+            for extension in ["png", "jpg", "jpeg"] {
+                let path = dir.join(&relative).join(format!("{}.{}", y, extension));
+                if path.is_file() {
+                    return Pixbuf::from_file(&path)
+                        .map_err(|e| warn!("Failed to load tile {:?}, {}", path, e))
+                        .ok();
+                }
+            }
+        }
+
+        self.download(z, x, y, &relative)
+    }
+
+    fn download(&self, z: u8, x: u32, y: u32, relative: &Path) -> Option<Pixbuf> {
+        let (url, client) = (self.url.as_ref()?, self.client.as_ref()?);
+        let url = url
+            .replace("{z}", &z.to_string())
+            .replace("{x}", &x.to_string())
+            .replace("{y}", &y.to_string());
+
+        let now = Instant::now();
+        let unreachable = self
+            .unreachable_until
+            .get()
+            .is_some_and(|until| now < until);
+        let failed = self
+            .failed
+            .borrow()
+            .get(&(z, x, y))
+            .is_some_and(|until| now < *until);
+        if unreachable || failed {
+            debug!(url, "Skipping tile that failed to download");
+            return None;
+        }
+        debug!(url, "Downloading tile");
+
+        let response = client
+            .get(&url)
+            .send()
+            .and_then(|response| response.error_for_status())
+            .and_then(|response| {
+                let extension = tile_extension(&url, response.headers());
+                response.bytes().map(|bytes| (extension, bytes))
+            });
+        let (extension, bytes) = match response {
+            Ok(response) => response,
+            Err(e) => {
+                warn!("Failed to download tile {}, {}", url, e);
+                if e.is_connect() || e.is_timeout() {
+                    self.unreachable_until.set(Some(now + DOWNLOAD_RETRY));
+                } else {
+                    let mut failed = self.failed.borrow_mut();
+                    failed.retain(|_, until| now < *until);
+                    failed.insert((z, x, y), now + DOWNLOAD_RETRY);
+                }
+                return None;
+            }
+        };
+
+        let dir = self.cache_dir.join(relative);
+        let path = dir.join(format!("{}.{}", y, extension));
+        if let Err(e) = fs::create_dir_all(&dir).and_then(|_| fs::write(&path, &bytes)) {
+            warn!("Failed to cache tile {:?}, {}", path, e);
+            return None;
+        }
+
+        Pixbuf::from_file(&path).ok()
+    }
+}
+
+/// The cache extension for a downloaded tile, from the content type or else
+/// the URL, so that JPEG tiles aren't stored as `.png`.
+fn tile_extension(url: &str, headers: &reqwest::header::HeaderMap) -> &'static str {
+    let content_type = headers
+        .get(reqwest::header::CONTENT_TYPE)
+        .and_then(|value| value.to_str().ok())
+        .unwrap_or_default();
+    if content_type.starts_with("image/jpeg") || content_type.starts_with("image/jpg") {
+        return "jpg";
+    }
+    if content_type.starts_with("image/png") {
+        return "png";
+    }
+
+    let path = url.split(['?', '#']).next().unwrap_or_default();
+    match Path::new(path).extension().and_then(|e| e.to_str()) {
+        Some(e) if e.eq_ignore_ascii_case("jpg") || e.eq_ignore_ascii_case("jpeg") => "jpg",
+        _ => "png",
+    }
+}
+
+/// Renders a `size` pixel square map centered on the location, with a pin in
+/// the middle. Returns `None` when none of the needed tiles are available.
+pub fn render_minimap(
+    tiles: &TileSource,
+    location: (f32, f32),
+    zoom: u8,
+    size: i32,
+) -> Option<Pixbuf> {
+    let zoom = zoom.min(MAX_ZOOM);
+    let (center_x, center_y) = world_pixel(location.0 as f64, location.1 as f64, zoom);
+    let tile_count = 1i64 << zoom;
+    let size = size.max(16) as usize;
+    let left = center_x.round() as i64 - size as i64 / 2;
+    let top = center_y.round() as i64 - size as i64 / 2;
+
+    let mut pixels: Vec<u8> = EMPTY_COLOR.repeat(size * size);
+    let mut found = false;
+    let tile_size = TILE_SIZE as i64;
+    for tile_y in top.div_euclid(tile_size)..=(top + size as i64 - 1).div_euclid(tile_size) {
+        if tile_y < 0 || tile_y >= tile_count {
+            continue;
+        }
+        for tile_x in left.div_euclid(tile_size)..=(left + size as i64 - 1).div_euclid(tile_size) {
+            // The world wraps around horizontally
+            let wrapped_x = tile_x.rem_euclid(tile_count);
+            let Some(tile) = tiles.tile(zoom, wrapped_x as u32, tile_y as u32) else {
+                continue;
+            };
+            // High DPI tiles
+            let tile = if tile.width() != TILE_SIZE || tile.height() != TILE_SIZE {
+                match tile.scale_simple(TILE_SIZE, TILE_SIZE, InterpType::Bilinear) {
+                    Some(tile) => tile,
+                    None => continue,
+                }
+            } else {
+                tile
+            };
+            found = true;
+
+            let tile_pixels = rgb_pixels(&tile);
+            for y in 0..tile_size {
+                let out_y = tile_y * tile_size + y - top;
+                if out_y < 0 || out_y >= size as i64 {
+                    continue;
+                }
+                let x0 = (left - tile_x * tile_size).clamp(0, tile_size);
+                let x1 = (left + size as i64 - tile_x * tile_size).clamp(0, tile_size);
+                if x1 <= x0 {
+                    continue;
+                }
+
+                let src = ((y * tile_size + x0) * 3) as usize;
+                let dst = ((out_y as usize * size) + (tile_x * tile_size + x0 - left) as usize) * 3;
+                let len = ((x1 - x0) * 3) as usize;
+                pixels[dst..dst + len].copy_from_slice(&tile_pixels[src..src + len]);
+            }
+        }
+    }
+
+    if !found {
+        debug!("No map tiles for location");
+        return None;
+    }
+
+    draw_pin(&mut pixels, size);
+
+    Some(Pixbuf::from_mut_slice(
+        pixels,
+        Colorspace::Rgb,
+        false,
+        8,
+        size as i32,
+        size as i32,
+        size as i32 * 3,
+    ))
+}
+
+/// Position of a location in pixels on the whole world map at `zoom`.
+fn world_pixel(latitude: f64, longitude: f64, zoom: u8) -> (f64, f64) {
+    let world = TILE_SIZE as f64 * (1u64 << zoom) as f64;
+    let latitude = latitude.clamp(-MAX_LATITUDE, MAX_LATITUDE).to_radians();
+    let x = (longitude + 180.0) / 360.0 * world;
+    let y = (1.0 - (latitude.tan() + 1.0 / latitude.cos()).ln() / PI) / 2.0 * world;
+    (x, y)
+}
+
+/// A filled circle with a white border in the middle of the map.
+fn draw_pin(pixels: &mut [u8], size: usize) {
+    let center = size as f64 / 2.0;
+    let outer = PIN_RADIUS + PIN_BORDER;
+    let from = (center - outer).floor().max(0.0) as usize;
+    let to = ((center + outer).ceil() as usize).min(size);
+
+    for y in from..to {
+        for x in from..to {
+            let distance =
+                ((x as f64 + 0.5 - center).powi(2) + (y as f64 + 0.5 - center).powi(2)).sqrt();
+            let color = if distance <= PIN_RADIUS {
+                PIN_COLOR
+            } else if distance <= outer {
+                [0xff, 0xff, 0xff]
+            } else {
+                continue;
+            };
+            let i = (y * size + x) * 3;
+            pixels[i..i + 3].copy_from_slice(&color);
+        }
+    }
+}
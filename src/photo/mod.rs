@@ -1,3 +1,12 @@
+pub mod background;
+pub mod camera;
+pub mod caption;
+pub mod collections;
+pub mod crop;
+pub mod date;
+pub mod layout;
+pub mod minimap;
 pub mod provider;
+pub mod saliency;
 pub use provider::Media;
 pub use provider::MediaProvider;
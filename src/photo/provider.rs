@@ -2,12 +2,12 @@ use std::{
     fs::{self, ReadDir},
     io::{self, Error},
     path::{Path, PathBuf},
-    sync::{Arc, Mutex},
+    sync::{mpsc, Arc, Mutex},
     thread,
     time::Duration,
 };
 
-use exif::{DateTime, In, Tag, Value};
+use exif::{In, Tag, Value};
 use gtk::{
     gdk_pixbuf::{Pixbuf, PixbufRotation},
     glib::Sender,
@@ -16,7 +16,31 @@ use rand::prelude::*;
 use serde::{Deserialize, Serialize};
 use tracing::{debug, instrument, span, warn, Level};
 
-use crate::{geocoder::Geocoder, utils::unsafe_wrapper::UnsafeSendSync};
+use crate::{
+    display::{
+        ambient::AmbientLightSource, power::DisplayPowerBackend, presence::PresenceSource,
+        schedule::DisplayScheduleConfig,
+    },
+    events,
+    geocoder::Geocoder,
+    gui::{
+        night::NightMode,
+        overlay::{default_overlays, OverlayConfig},
+    },
+    mqtt::MqttTransport,
+    utils::unsafe_wrapper::UnsafeSendSync,
+    weather::{WeatherSource, WeatherUnits},
+};
+
+use super::background::{Background, BackgroundFill};
+use super::camera::{CameraInfo, CameraInfoMode};
+use super::caption::read_caption;
+use super::collections::{load_collections, remove_entry, write_collections, Collections};
+use super::crop::{smart_crop, FitMode};
+use super::date::{CaptureDate, PhotoDateStyle};
+use super::layout::{collage_layout, CollageLayout, Rect};
+use super::minimap::{render_minimap, TileSource};
+use super::saliency::EnergyMap;
 
 #[derive(Clone, Debug)]
 pub enum Media {
@@ -24,19 +48,40 @@ pub enum Media {
         path: PathBuf,
         orientation: u32,
         location: Option<(f32, f32)>,
-        date: Option<String>,
+        date: Option<CaptureDate>,
+        caption: Option<String>,
+        camera: Option<CameraInfo>,
     },
     Video {
         path: PathBuf,
     },
 }
 
+impl Media {
+    pub fn path(&self) -> &Path {
+        match self {
+            Media::Photo { path, .. } | Media::Video { path } => path,
+        }
+    }
+}
+
 pub enum MediaMessage {
     Photo {
         photo: Media,
         photo_data: PhotoData,
         address: Result<String, String>,
     },
+    PhotoPair {
+        photos: (Media, Media),
+        photo_data: (PhotoData, PhotoData),
+        address: Result<String, String>,
+    },
+    Collage {
+        photos: Vec<Media>,
+        photo_data: Vec<PhotoData>,
+        layout: Vec<Rect>,
+        address: Result<String, String>,
+    },
     Video {
         video: Media,
     },
@@ -44,14 +89,30 @@ pub enum MediaMessage {
 
 pub struct PhotoData {
     pub pixbuf: Arc<UnsafeSendSync<Pixbuf>>,
+    pub background: Background,
+    /// Only computed when an overlay hides itself over busy areas
+    pub energy: Option<EnergyMap>,
+    /// Map of the location shown in the overlay
+    pub minimap: Option<Arc<UnsafeSendSync<Pixbuf>>>,
 }
 
-pub fn load_config() -> Config {
-    let mut path = Path::new(".config.json5");
-    if !path.exists() {
-        path = Path::new("/etc/pi-photo-frame.json5");
+pub fn config_path() -> &'static Path {
+    let path = Path::new(".config.json5");
+    if path.exists() {
+        path
+    } else {
+        Path::new("/etc/pi-photo-frame.json5")
     }
-    json5::from_str(&fs::read_to_string(path).unwrap()).unwrap()
+}
+
+pub fn load_config() -> Config {
+    try_load_config().unwrap()
+}
+
+pub fn try_load_config() -> Result<Config, String> {
+    let path = config_path();
+    let text = fs::read_to_string(path).map_err(|e| format!("{:?}: {}", path, e))?;
+    json5::from_str(&text).map_err(|e| format!("{:?}: {}", path, e))
 }
 
 pub fn load_failed_files() -> FailedFiles {
@@ -78,10 +139,312 @@ pub struct Config {
     pub mqtt: bool,
     pub mqtt_host: String,
     pub mqtt_topic: String,
+    // Commands are read from <mqtt_base_topic>/cmd/<command>
+    #[serde(default = "default_mqtt_base_topic")]
+    pub mqtt_base_topic: String,
     pub mqtt_user: Option<String>,
     pub mqtt_password: Option<String>,
+    // Defaults to 1883, or 8883 with TLS, or 80/443 for WebSockets
+    #[serde(default)]
+    pub mqtt_port: Option<u16>,
+    // Defaults to pi-photo-frame-<hostname>
+    #[serde(default)]
+    pub mqtt_client_id: Option<String>,
+    #[serde(default = "default_mqtt_keep_alive_seconds")]
+    pub mqtt_keep_alive_seconds: u32,
+    // 0, 1 or 2, for subscriptions and published state
+    #[serde(default)]
+    pub mqtt_qos: u8,
+    // TLS with the system certificates, or mqtt_ca_file
+    #[serde(default)]
+    pub mqtt_tls: bool,
+    #[serde(default)]
+    pub mqtt_ca_file: Option<String>,
+    #[serde(default)]
+    pub mqtt_client_cert: Option<String>,
+    #[serde(default)]
+    pub mqtt_client_key: Option<String>,
+    #[serde(default)]
+    pub mqtt_transport: MqttTransport,
+    #[serde(default = "default_mqtt_websocket_path")]
+    pub mqtt_websocket_path: String,
+    // Serve the HTTP API on this address, e.g. "0.0.0.0:8080". Off when unset.
+    #[serde(default)]
+    pub http_address: Option<String>,
+    // Required as a bearer token or ?token= when set, the web UI is off without it
+    #[serde(default)]
+    pub http_token: Option<String>,
+    // Photos uploaded through the web UI are saved here
+    #[serde(default)]
+    pub web_upload_dir: Option<String>,
+    // Also export the D-Bus interface on the system bus, not only the session bus
+    #[serde(default)]
+    pub dbus_system_bus: bool,
+    // Publish Home Assistant MQTT discovery configs under <mqtt_discovery_prefix>
+    #[serde(default)]
+    pub mqtt_discovery: bool,
+    #[serde(default = "default_mqtt_discovery_prefix")]
+    pub mqtt_discovery_prefix: String,
     pub reverse_geocode: bool,
     pub mapbox_api_key: String,
+    #[serde(default)]
+    pub background_fill: BackgroundFill,
+    // Any CSS colour, used for the `color` fill and when a photo has no background
+    #[serde(default)]
+    pub background_color: Option<String>,
+    // Show two portrait photos side by side on a landscape screen
+    #[serde(default)]
+    pub portrait_pairing: bool,
+    // Chance (0.0 - 1.0) that a slide is a collage of photos from the same event
+    #[serde(default)]
+    pub collage_frequency: f32,
+    #[serde(default = "default_collage_max_photos")]
+    pub collage_max_photos: usize,
+    // Photos further apart in time than this don't go in the same collage
+    #[serde(default = "default_collage_max_gap_minutes")]
+    pub collage_max_gap_minutes: u32,
+    #[serde(default)]
+    pub collage_layout: CollageLayout,
+    #[serde(default)]
+    pub fit_mode: FitMode,
+    // In fill mode, photos that would lose more than this (0.0 - 1.0) are fitted instead
+    #[serde(default = "default_fill_max_crop")]
+    pub fill_max_crop: f64,
+    // Overlay widgets to show and where. Widgets that are not listed are hidden.
+    #[serde(default = "default_overlays")]
+    pub overlays: Vec<OverlayConfig>,
+    #[serde(default)]
+    pub photo_date_style: PhotoDateStyle,
+    #[serde(default = "default_photo_date_format")]
+    pub photo_date_format: String,
+    // Camera and exposure details, shown under the photo path
+    #[serde(default)]
+    pub camera_info: CameraInfoMode,
+    // Slippy-map tiles for the map overlay, laid out as {z}/{x}/{y}.png
+    #[serde(default)]
+    pub minimap_tile_dir: Option<String>,
+    // Downloads missing tiles into minimap_cache_dir, e.g.
+    // "https://tile.openstreetmap.org/{z}/{x}/{y}.png"
+    #[serde(default)]
+    pub minimap_tile_url: Option<String>,
+    #[serde(default = "default_minimap_cache_dir")]
+    pub minimap_cache_dir: String,
+    #[serde(default = "default_minimap_zoom")]
+    pub minimap_zoom: u8,
+    #[serde(default = "default_minimap_size")]
+    pub minimap_size: i32,
+    #[serde(default)]
+    pub weather_provider: Option<WeatherSource>,
+    #[serde(default = "default_weather_url")]
+    pub weather_url: String,
+    #[serde(default)]
+    pub weather_api_key: Option<String>,
+    #[serde(default)]
+    pub weather_latitude: Option<f64>,
+    #[serde(default)]
+    pub weather_longitude: Option<f64>,
+    #[serde(default)]
+    pub weather_units: WeatherUnits,
+    #[serde(default = "default_weather_forecast_count")]
+    pub weather_forecast_count: usize,
+    #[serde(default = "default_weather_refresh_minutes")]
+    pub weather_refresh_minutes: u32,
+    // The widget hides once the weather is older than this
+    #[serde(default = "default_weather_max_age_minutes")]
+    pub weather_max_age_minutes: u32,
+    #[serde(default)]
+    pub weather_mqtt_topic: Option<String>,
+    // Paths or URLs of .ics calendars
+    #[serde(default)]
+    pub calendar_sources: Vec<String>,
+    // Number of days shown, starting with today
+    #[serde(default = "default_calendar_days")]
+    pub calendar_days: u32,
+    #[serde(default = "default_calendar_max_events")]
+    pub calendar_max_events: usize,
+    #[serde(default = "default_calendar_refresh_minutes")]
+    pub calendar_refresh_minutes: u32,
+    // Format of the clock, overrides clock_24h and clock_seconds
+    #[serde(default)]
+    pub clock_time_format: Option<String>,
+    #[serde(default = "default_clock_date_format")]
+    pub clock_date_format: String,
+    #[serde(default = "default_clock_24h")]
+    pub clock_24h: bool,
+    #[serde(default)]
+    pub clock_seconds: bool,
+    // Locale used for day and month names, e.g. "ro_RO.UTF-8"
+    #[serde(default)]
+    pub clock_locale: Option<String>,
+    // Time zone of an extra clock, e.g. "America/New_York"
+    #[serde(default)]
+    pub second_clock_timezone: Option<String>,
+    #[serde(default)]
+    pub second_clock_label: Option<String>,
+    // Night mode period, e.g. "22:00" to "07:00"
+    #[serde(default)]
+    pub night_start: Option<String>,
+    #[serde(default)]
+    pub night_end: Option<String>,
+    #[serde(default)]
+    pub night_mode: NightMode,
+    #[serde(default = "default_night_brightness")]
+    pub night_brightness: f64,
+    // 0 keeps the colors, 1 is a full sepia tint
+    #[serde(default = "default_night_warmth")]
+    pub night_warmth: f64,
+    // Powers the display off and pauses the slideshow outside these times
+    #[serde(default)]
+    pub display_schedule: Option<DisplayScheduleConfig>,
+    #[serde(default)]
+    pub display_power: DisplayPowerBackend,
+    // Backlight in /sys/class/backlight, the first one when missing
+    #[serde(default)]
+    pub display_backlight: Option<String>,
+    // Output for the wlr backend, all of them when missing
+    #[serde(default)]
+    pub display_output: Option<String>,
+    // Backlight brightness set at startup, from 0.0 to 1.0
+    #[serde(default)]
+    pub display_brightness: Option<f64>,
+    #[serde(default)]
+    pub ambient_light_source: Option<AmbientLightSource>,
+    // IIO channel file, found automatically when missing
+    #[serde(default)]
+    pub ambient_light_sensor: Option<String>,
+    #[serde(default)]
+    pub ambient_light_mqtt_topic: Option<String>,
+    // [lux, brightness] points, interpolated in between
+    #[serde(default = "default_ambient_light_curve")]
+    pub ambient_light_curve: Vec<[f64; 2]>,
+    // Weight of each new reading, from 0.01 (slow) to 1 (no smoothing)
+    #[serde(default = "default_ambient_light_smoothing")]
+    pub ambient_light_smoothing: f64,
+    // The display turns off below this light level
+    #[serde(default)]
+    pub ambient_light_off_lux: Option<f64>,
+    #[serde(default = "default_ambient_light_interval_seconds")]
+    pub ambient_light_interval_seconds: u32,
+    #[serde(default)]
+    pub presence_source: Option<PresenceSource>,
+    // GPIO character device, e.g. /dev/gpiochip0. Without it presence_gpio is
+    // read through /sys/class/gpio
+    #[serde(default)]
+    pub presence_gpio_chip: Option<String>,
+    // Line offset on presence_gpio_chip, or the GPIO number in /sys/class/gpio
+    #[serde(default)]
+    pub presence_gpio: Option<u32>,
+    #[serde(default)]
+    pub presence_gpio_active_low: bool,
+    #[serde(default)]
+    pub presence_mqtt_topic: Option<String>,
+    // The display turns off after nobody was seen for this long
+    #[serde(default = "default_presence_timeout_minutes")]
+    pub presence_timeout_minutes: u32,
+}
+
+fn default_mqtt_base_topic() -> String {
+    "pi-photo-frame".to_string()
+}
+
+fn default_mqtt_keep_alive_seconds() -> u32 {
+    5
+}
+
+fn default_mqtt_websocket_path() -> String {
+    "/mqtt".to_string()
+}
+
+fn default_mqtt_discovery_prefix() -> String {
+    "homeassistant".to_string()
+}
+
+fn default_ambient_light_curve() -> Vec<[f64; 2]> {
+    vec![[0.0, 0.05], [10.0, 0.2], [100.0, 0.6], [500.0, 1.0]]
+}
+
+fn default_ambient_light_smoothing() -> f64 {
+    0.3
+}
+
+fn default_ambient_light_interval_seconds() -> u32 {
+    5
+}
+
+fn default_presence_timeout_minutes() -> u32 {
+    10
+}
+
+fn default_night_brightness() -> f64 {
+    0.3
+}
+
+fn default_night_warmth() -> f64 {
+    0.6
+}
+
+fn default_minimap_cache_dir() -> String {
+    "/var/cache/pi-photo-frame/tiles".to_string()
+}
+
+fn default_minimap_zoom() -> u8 {
+    12
+}
+
+fn default_minimap_size() -> i32 {
+    240
+}
+
+fn default_weather_url() -> String {
+    "https://api.openweathermap.org/data/2.5".to_string()
+}
+
+fn default_weather_forecast_count() -> usize {
+    3
+}
+
+fn default_weather_refresh_minutes() -> u32 {
+    15
+}
+
+fn default_weather_max_age_minutes() -> u32 {
+    60
+}
+
+fn default_calendar_days() -> u32 {
+    2
+}
+
+fn default_calendar_max_events() -> usize {
+    5
+}
+
+fn default_calendar_refresh_minutes() -> u32 {
+    15
+}
+
+fn default_photo_date_format() -> String {
+    "%Y-%m-%d %H:%M:%S".to_string()
+}
+
+fn default_clock_date_format() -> String {
+    "%A, %d %B %Y".to_string()
+}
+
+fn default_clock_24h() -> bool {
+    true
+}
+
+fn default_fill_max_crop() -> f64 {
+    0.3
+}
+
+fn default_collage_max_photos() -> usize {
+    MAX_COLLAGE_PHOTOS
+}
+
+fn default_collage_max_gap_minutes() -> u32 {
+    120
 }
 
 #[derive(Deserialize, Serialize, Default, Debug, Clone)]
@@ -90,6 +453,109 @@ pub struct FailedFiles {
     pub current: String,
 }
 
+/// How many photos from the same folder are checked when looking for a
+/// portrait partner.
+const PAIRING_CANDIDATES: usize = 20;
+
+const MIN_COLLAGE_PHOTOS: usize = 3;
+const MAX_COLLAGE_PHOTOS: usize = 6;
+/// How many photos from the same folder are read when building a collage.
+const COLLAGE_CANDIDATES: usize = 40;
+const COLLAGE_SPACING: i32 = 8;
+
+/// Longest side of the copy used to find busy areas under the overlays.
+const ENERGY_MAP_SIZE: i32 = 96;
+
+pub const PHOTO_EXTENSIONS: [&str; 3] = ["jpg", "jpeg", "png"];
+
+/// How many shown photos are remembered for going back.
+const HISTORY_SIZE: usize = 50;
+/// Album name for the photos marked as favorites.
+pub const FAVORITES_ALBUM: &str = "favorites";
+/// Album name for photos from all paths.
+pub const ALL_ALBUM: &str = "all";
+
+/// Albums are named after the last folder of their path.
+pub fn album_name(path: &str) -> &str {
+    Path::new(path)
+        .file_name()
+        .and_then(|name| name.to_str())
+        .unwrap_or(path)
+}
+
+/// Decimal degrees from the EXIF degrees, minutes and seconds. South and west
+/// are stored as positive values with a separate reference and come out
+/// negative, which reverse geocoding and the seasons of southern photos need.
+fn gps_degrees(exif: &exif::Exif, tag: Tag, reference: Tag) -> Option<f32> {
+    let Value::Rational(ref parts) = exif.get_field(tag, In::PRIMARY)?.value else {
+        return None;
+    };
+    if parts.is_empty() {
+        return None;
+    }
+    let degrees: f32 = parts
+        .iter()
+        .zip([1.0, 60.0, 3600.0])
+        .map(|(part, scale)| part.num as f32 / part.denom as f32 / scale)
+        .sum();
+
+    let negative = exif
+        .get_field(reference, In::PRIMARY)
+        .is_some_and(|field| match field.value {
+            Value::Ascii(ref v) => v
+                .first()
+                .is_some_and(|r| r.starts_with(b"S") || r.starts_with(b"W")),
+            _ => false,
+        });
+    Some(if negative { -degrees } else { degrees })
+}
+
+/// Every name `MediaProvider::set_album` accepts.
+pub fn album_names(config: &Config) -> Vec<String> {
+    let mut names = vec![ALL_ALBUM.to_string()];
+    names.extend(config.paths.iter().map(|path| album_name(path).to_string()));
+    names.push(FAVORITES_ALBUM.to_string());
+    names
+}
+
+/// The canonical path when it is under the configured paths or the upload
+/// folder, the only files shown or served on request.
+pub fn allowed_path(config: &Config, path: &Path) -> Option<PathBuf> {
+    let path = path.canonicalize().ok()?;
+    config
+        .paths
+        .iter()
+        .chain(config.web_upload_dir.iter())
+        .filter_map(|root| Path::new(root).canonicalize().ok())
+        .any(|root| path.starts_with(root))
+        .then_some(path)
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+enum Album {
+    /// Index into `Config::paths`
+    Path(usize),
+    Favorites,
+}
+
+/// Photos for a collage, chosen under the provider lock.
+#[derive(Debug)]
+pub struct CollagePick {
+    seed: PathBuf,
+    /// In random order, at most `COLLAGE_CANDIDATES`
+    candidates: Vec<PathBuf>,
+    count: usize,
+    /// Seconds
+    max_gap: i64,
+}
+
+/// Shown by the worker right away instead of waiting for the interval.
+#[derive(Debug)]
+enum MediaRequest {
+    Next,
+    Show(PathBuf),
+}
+
 #[derive(Default, Debug)]
 pub struct MediaProvider {
     config: Config,
@@ -97,19 +563,39 @@ pub struct MediaProvider {
     photo_valid_extensions: Vec<String>,
     video_valid_extensions: Vec<String>,
     pub paused: bool,
+    /// Night mode only shows the clock, so no photos are needed
+    pub clock_only: bool,
+    pub screen_size: (i32, i32),
+    /// Seconds between photos, starts at `transition_time`
+    pub interval: u32,
+    album: Option<Album>,
+    collections: Collections,
+    /// Recently shown photos and videos, the current one last
+    history: Vec<PathBuf>,
+    requested: Option<MediaRequest>,
+    /// Wakes the worker before the interval is over
+    wake: Option<mpsc::Sender<()>>,
 }
 
 impl MediaProvider {
     pub fn new(config: Config, failed_files: FailedFiles) -> Self {
         MediaProvider {
+            interval: config.transition_time,
             config,
             failed_files,
-            photo_valid_extensions: vec!["jpg".to_string(), "jpeg".to_string(), "png".to_string()],
+            photo_valid_extensions: PHOTO_EXTENSIONS.iter().map(|e| e.to_string()).collect(),
             video_valid_extensions: vec![
                 // "mov".to_string(),
                 // "mp4".to_string(),
             ],
             paused: false,
+            clock_only: false,
+            screen_size: (0, 0),
+            album: None,
+            collections: load_collections(),
+            history: vec![],
+            requested: None,
+            wake: None,
         }
     }
 
@@ -117,9 +603,13 @@ impl MediaProvider {
     pub fn start_worker(this: Arc<Mutex<MediaProvider>>, media_sender: Sender<MediaMessage>) {
         debug!("Starting worker thread");
         let config_clone = this.clone().lock().unwrap().config.clone();
+        let (wake_sender, wake_receiver) = mpsc::channel();
+        this.clone().lock().unwrap().wake = Some(wake_sender);
         thread::spawn(move || {
             debug!("Started worker thread");
-            let geocoder = Geocoder::new(config_clone.mapbox_api_key);
+            // Connections keep the settings from the start
+            let geocoder = Geocoder::new(config_clone.mapbox_api_key.clone());
+            let tiles = TileSource::new(&config_clone);
 
             let this_clone = this.clone();
 
@@ -145,41 +635,122 @@ impl MediaProvider {
                 let span = span!(Level::TRACE, "get_photo_thread");
                 let _enter = span.enter();
 
-                thread::sleep(Duration::from_secs(config_clone.transition_time.into()));
-                let media = this.clone().lock().unwrap().get_media();
+                // Commands like next and previous wake the worker early
+                let interval = this.clone().lock().unwrap().interval;
+                let woken = wake_receiver
+                    .recv_timeout(Duration::from_secs(interval.into()))
+                    .is_ok();
+                let requested = this.clone().lock().unwrap().requested.take();
+                // Woken without a request to wait with a new interval
+                if woken && requested.is_none() {
+                    continue;
+                }
+                // Read on every pass to pick up reloaded settings
+                let config = this.clone().lock().unwrap().config.clone();
+
+                if requested.is_none()
+                    && config.collage_frequency > 0.0
+                    && rand::random::<f32>() < config.collage_frequency
+                {
+                    let pick = this.clone().lock().unwrap().get_collage();
+                    let collage = match pick {
+                        Ok(Some(pick)) => MediaProvider::read_collage(pick),
+                        Ok(None) => Ok(None),
+                        Err(e) => Err(e),
+                    };
+                    match collage {
+                        Ok(Some(photos)) => {
+                            let screen_size = this.clone().lock().unwrap().screen_size;
+                            if let Some(collage_obj) = MediaProvider::prepare_collage(
+                                photos,
+                                screen_size,
+                                &config,
+                                &geocoder,
+                                &tiles,
+                            ) {
+                                debug!("Sending collage to UI");
+                                let res = media_sender.send(collage_obj);
+                                if let Err(e) = res {
+                                    warn!("Failed to send collage_obj between threads {}", e);
+                                }
+                                continue;
+                            }
+                        }
+                        Ok(None) => {}
+                        Err(e) => {
+                            warn!("Error getting collage, {}", e);
+                            events::emit_error(format!("Error getting collage, {}", e));
+                        }
+                    }
+                }
+
+                let media = match requested {
+                    Some(request) => this.clone().lock().unwrap().get_requested(request),
+                    None => this.clone().lock().unwrap().get_media(),
+                };
                 debug!("Got media");
+                if let Ok(Some(media)) = &media {
+                    this.clone().lock().unwrap().remember(media);
+                }
                 match media {
                     Ok(Some(Media::Photo {
                         ref path,
                         orientation,
                         location,
-                        date: _,
+                        ref date,
+                        ..
                     })) => {
-                        let image_data = Pixbuf::from_file(path);
-                        if let Err(err) = image_data {
-                            warn!("Loading image failed {:?}", err);
+                        let Some(mut new_pixbuf) = MediaProvider::load_pixbuf(path, orientation)
+                        else {
                             continue;
-                        }
-
-                        let pixbuf = Arc::new(UnsafeSendSync::new(image_data.unwrap()));
+                        };
 
-                        if pixbuf.height() <= 0 || pixbuf.width() <= 0 {
-                            warn!("Corrupted image {:?}", path);
-                            continue;
+                        let (screen_width, screen_height) =
+                            this.clone().lock().unwrap().screen_size;
+                        let mut partner = None;
+                        if config.portrait_pairing
+                            && screen_width > screen_height
+                            && new_pixbuf.height() > new_pixbuf.width()
+                        {
+                            debug!("Looking for a portrait partner");
+                            // Only listing the folder needs the lock, not reading the photos
+                            let candidates = this.clone().lock().unwrap().folder_candidates(path);
+                            partner = MediaProvider::find_portrait_partner(candidates, date)
+                                .and_then(|partner| match partner {
+                                    Media::Photo {
+                                        ref path,
+                                        orientation,
+                                        ..
+                                    } => MediaProvider::load_pixbuf(path, orientation)
+                                        .map(|pixbuf| (partner.clone(), pixbuf)),
+                                    _ => None,
+                                });
                         }
 
-                        let new_pixbuf = MediaProvider::rotate_photo(pixbuf, orientation);
-
-                        let mut address_message = Err("Not set".into());
-                        if config_clone.reverse_geocode {
-                            if let Some(location) = location {
-                                debug!("Geolocating");
-                                let address = geocoder.reverse_geocode(location.0, location.1);
-                                address_message = address;
-                                debug!("Finished geolocating");
+                        let mut background_fill = config.background_fill;
+                        if partner.is_none() && config.fit_mode == FitMode::Fill {
+                            debug!("Cropping photo");
+                            if let Some(cropped) = smart_crop(
+                                &new_pixbuf,
+                                (screen_width, screen_height),
+                                config.fill_max_crop,
+                            ) {
+                                new_pixbuf = Arc::new(UnsafeSendSync::new(cropped));
+                                // Nothing shows around a cropped photo
+                                background_fill = BackgroundFill::Color;
                             }
                         }
 
+                        debug!("Preparing background");
+                        let background = Background::for_photo(
+                            background_fill,
+                            &new_pixbuf,
+                            (screen_width, screen_height),
+                        );
+
+                        let address_message = MediaProvider::geocode(&config, &geocoder, location);
+                        let minimap = MediaProvider::minimap(&config, &tiles, location);
+
                         debug!("Saving photo path");
                         let mut failed_files = this.clone().lock().unwrap().failed_files.clone();
                         failed_files.current = path.to_str().unwrap().to_string();
@@ -189,18 +760,44 @@ impl MediaProvider {
                             warn!("Failed to write failed_files {}", e);
                         }
 
-                        let photo_obj = MediaMessage::Photo {
-                            photo: media.unwrap().unwrap().clone(),
-                            photo_data: PhotoData {
-                                pixbuf: new_pixbuf.clone(),
+                        let photo = media.unwrap().unwrap().clone();
+                        let mut energy = None;
+                        if config.overlays.iter().any(|o| o.auto_hide) {
+                            energy = EnergyMap::from_pixbuf(&new_pixbuf, ENERGY_MAP_SIZE);
+                        }
+
+                        let photo_data = PhotoData {
+                            pixbuf: new_pixbuf.clone(),
+                            background,
+                            energy,
+                            minimap,
+                        };
+
+                        let photo_obj = match partner {
+                            Some((partner, partner_pixbuf)) => MediaMessage::PhotoPair {
+                                photos: (photo, partner),
+                                photo_data: (
+                                    photo_data,
+                                    PhotoData {
+                                        pixbuf: partner_pixbuf,
+                                        background: Background::None,
+                                        energy: None,
+                                        minimap: None,
+                                    },
+                                ),
+                                address: address_message,
+                            },
+                            None => MediaMessage::Photo {
+                                photo,
+                                photo_data,
+                                address: address_message,
                             },
-                            address: address_message,
                         };
 
                         debug!("Sending photo to UI");
                         let res = media_sender.send(photo_obj);
                         if let Err(e) = res {
-                            println!("Failed to send photo_obj between threads {}", e);
+                            warn!("Failed to send photo_obj between threads {}", e);
                         }
                     }
 
@@ -211,7 +808,7 @@ impl MediaProvider {
 
                         let res = media_sender.send(video_obj);
                         if let Err(e) = res {
-                            println!("Failed to send video_obj between threads {}", e);
+                            warn!("Failed to send video_obj between threads {}", e);
                         }
                     }
                     Ok(None) => {
@@ -219,7 +816,9 @@ impl MediaProvider {
                         // Most likely paused, don't do anything.
                     }
                     _ => {
-                        println!("Error getting photo, {}", media.unwrap_err());
+                        let e = media.unwrap_err();
+                        warn!("Error getting photo, {}", e);
+                        events::emit_error(format!("Error getting photo, {}", e));
                     }
                 }
             }
@@ -228,17 +827,46 @@ impl MediaProvider {
 
     #[instrument(skip(self))]
     pub fn get_media(&mut self) -> Result<Option<Media>, io::Error> {
-        if self.paused {
+        if self.paused || self.clock_only {
             return Ok(None);
         }
 
+        self.pick_media().map(Some)
+    }
+
+    /// Media for a next, previous or show command, shown even while paused.
+    fn get_requested(&mut self, request: MediaRequest) -> Result<Option<Media>, io::Error> {
+        if self.clock_only {
+            return Ok(None);
+        }
+
+        match request {
+            MediaRequest::Next => self.pick_media().map(Some),
+            MediaRequest::Show(path) => {
+                self.remove_current_failed_photo();
+                self.media_at(path).map(Some)
+            }
+        }
+    }
+
+    /// A random photo or video from the current album.
+    fn pick_media(&mut self) -> Result<Media, io::Error> {
         debug!("Remove current photo from failed");
         self.remove_current_failed_photo();
 
         let mut rng = rand::thread_rng();
-        let index = rng.gen_range(0..self.config.paths.len());
-
-        let exifreader = exif::Reader::new();
+        if self.album == Some(Album::Favorites) {
+            let path = self
+                .collections
+                .favorites
+                .iter()
+                .filter_map(|path| allowed_path(&self.config, Path::new(path)))
+                .filter(|path| path.is_file() && !self.collections.is_hidden(path))
+                .choose(&mut rng)
+                .ok_or_else(|| io::Error::new(io::ErrorKind::NotFound, "No favorites"))?;
+            return self.media_at(path);
+        }
+        let index = self.album_index(&mut rng);
 
         for t in 0..5 {
             debug!(current_try = t, "Trying to get a valid photo");
@@ -251,7 +879,6 @@ impl MediaProvider {
                 .collect();
 
             let random_media_path = MediaProvider::get_random_entry(dir, all_extensions)?;
-            let random_media_path_clone = random_media_path.clone();
 
             let extension = random_media_path.extension().unwrap().to_str().unwrap();
 
@@ -265,107 +892,645 @@ impl MediaProvider {
                 continue;
             }
 
+            if self.collections.is_hidden(&random_media_path) {
+                debug!("Found a hidden file, try again");
+                continue;
+            }
+
             if self
                 .photo_valid_extensions
                 .contains(&extension.to_lowercase())
             {
                 debug!("Found a valid photo");
-                let file = std::fs::File::open(random_media_path)?;
-                let mut bufreader = std::io::BufReader::new(file);
-                let exif = exifreader
-                    .read_from_container(&mut bufreader)
-                    .map_err(|e| io::Error::new(io::ErrorKind::Other, e));
-
-                if exif.is_err() {
-                    debug!("No exif data");
-                    return Ok(Some(Media::Photo {
-                        path: random_media_path_clone,
-                        orientation: 0,
-                        location: None,
-                        date: None,
-                    }));
-                }
+                return MediaProvider::read_photo(random_media_path, true);
+            } else {
+                return Ok(Media::Video {
+                    path: random_media_path,
+                });
+            }
+        }
 
-                let exif_obj = exif.unwrap();
+        return Err(io::Error::new(
+            io::ErrorKind::NotFound,
+            "No valid photo found",
+        ));
+    }
 
-                let orientation = match exif_obj.get_field(Tag::Orientation, In::PRIMARY) {
-                    Some(orientation) => match orientation.value.get_uint(0) {
-                        Some(v @ 1..=8) => v,
-                        _ => 1,
-                    },
-                    None => 1,
-                };
-                debug!(orientation, "Found orientation");
-
-                let latitude = match exif_obj.get_field(Tag::GPSLatitude, In::PRIMARY) {
-                    Some(latitude) => match latitude.value {
-                        Value::Rational(ref v) if !v.is_empty() => Some(v),
-                        _ => None,
-                    },
-                    None => None,
-                };
+    /// Picks 3 to `collage_max_photos` photos from one folder to be read by
+    /// [`MediaProvider::read_collage`], outside the lock.
+    #[instrument(skip(self))]
+    pub fn get_collage(&mut self) -> Result<Option<CollagePick>, io::Error> {
+        if self.paused || self.clock_only || self.album == Some(Album::Favorites) {
+            return Ok(None);
+        }
 
-                let longitude = match exif_obj.get_field(Tag::GPSLongitude, In::PRIMARY) {
-                    Some(longitude) => match longitude.value {
-                        Value::Rational(ref v) if !v.is_empty() => Some(v),
-                        _ => None,
-                    },
-                    None => None,
-                };
+        self.remove_current_failed_photo();
 
-                let mut location: Option<(f32, f32)> = None;
+        let mut rng = rand::thread_rng();
+        let index = self.album_index(&mut rng);
+        let dir = fs::read_dir(self.config.paths[index].clone())?;
+        let seed = MediaProvider::get_random_entry(dir, self.photo_valid_extensions.clone())?;
+        if !self.is_valid_photo(&seed) {
+            debug!("Collage seed is hidden or failed");
+            return Ok(None);
+        }
+        let mut candidates = self.folder_candidates(&seed);
 
-                if let Some(lat) = latitude {
-                    if let Some(lon) = longitude {
-                        let lat_dec: f32 = lat[0].num as f32 / lat[0].denom as f32
-                            + (lat[1].num as f32 / lat[1].denom as f32) / 60.0
-                            + (lat[2].num as f32 / lat[2].denom as f32) / 3600.0;
-                        debug!(lat_dec, "Found latitude");
+        let max_photos = self
+            .config
+            .collage_max_photos
+            .clamp(MIN_COLLAGE_PHOTOS, MAX_COLLAGE_PHOTOS);
+        let count = rng
+            .gen_range(MIN_COLLAGE_PHOTOS..=max_photos)
+            .min(candidates.len() + 1);
+        if count < MIN_COLLAGE_PHOTOS {
+            debug!("Not enough photos in folder for a collage");
+            return Ok(None);
+        }
 
-                        let lon_dec: f32 = lon[0].num as f32 / lon[0].denom as f32
-                            + (lon[1].num as f32 / lon[1].denom as f32) / 60.0
-                            + (lon[2].num as f32 / lon[2].denom as f32) / 3600.0;
-                        debug!(lon_dec, "Found longitude");
+        candidates.truncate(COLLAGE_CANDIDATES);
+        Ok(Some(CollagePick {
+            seed,
+            candidates,
+            count,
+            max_gap: self.config.collage_max_gap_minutes as i64 * 60,
+        }))
+    }
 
-                        location = Some((lat_dec, lon_dec));
+    /// Reads the picked photos, preferring the ones taken closest in time to
+    /// the seed photo.
+    fn read_collage(pick: CollagePick) -> Result<Option<Vec<Media>>, io::Error> {
+        let seed = MediaProvider::read_photo(pick.seed, false)?;
+        let seed_time = MediaProvider::timestamp(&seed);
+        let max_gap = pick.max_gap;
+
+        let mut photos: Vec<(Option<i64>, Media)> = pick
+            .candidates
+            .into_iter()
+            .filter_map(|candidate| MediaProvider::read_photo(candidate, false).ok())
+            .map(|photo| (MediaProvider::timestamp(&photo), photo))
+            .filter(|(time, _)| match (seed_time, time) {
+                (Some(seed_time), Some(time)) => (seed_time - time).abs() <= max_gap,
+                // Photos without a date only share the folder
+                _ => true,
+            })
+            .collect();
+
+        // Closest in time first, undated photos last
+        photos.sort_by_key(|(time, _)| match (seed_time, time) {
+            (Some(seed_time), Some(time)) => (seed_time - time).abs(),
+            _ => i64::MAX,
+        });
+
+        let mut collage = vec![seed];
+        collage.extend(
+            photos
+                .into_iter()
+                .map(|(_, photo)| photo)
+                .take(pick.count - 1),
+        );
+        if collage.len() < MIN_COLLAGE_PHOTOS {
+            debug!("Not enough photos close in time for a collage");
+            return Ok(None);
+        }
+
+        let mut collage: Vec<Media> = collage
+            .into_iter()
+            .map(MediaProvider::with_caption)
+            .collect();
+        collage.sort_by_key(MediaProvider::timestamp);
+        Ok(Some(collage))
+    }
+
+    fn prepare_collage(
+        photos: Vec<Media>,
+        screen_size: (i32, i32),
+        config: &Config,
+        geocoder: &Geocoder,
+        tiles: &Option<TileSource>,
+    ) -> Option<MediaMessage> {
+        let mut photos: Vec<(Media, f64)> = photos
+            .into_iter()
+            .filter_map(|photo| match photo {
+                Media::Photo {
+                    ref path,
+                    orientation,
+                    ..
+                } => MediaProvider::aspect_ratio(path, orientation).map(|a| (photo.clone(), a)),
+                _ => None,
+            })
+            .collect();
+        // Laid out again without the photos that failed to load, so that
+        // they don't leave holes
+        let loaded = loop {
+            if photos.len() < MIN_COLLAGE_PHOTOS {
+                warn!("Not enough photos loaded for a collage");
+                return None;
+            }
+
+            let aspects: Vec<f64> = photos.iter().map(|(_, aspect)| *aspect).collect();
+            let layout = collage_layout(
+                config.collage_layout,
+                &aspects,
+                screen_size.0,
+                screen_size.1,
+                COLLAGE_SPACING,
+            );
+
+            let count = photos.len();
+            let mut loaded = vec![];
+            let mut remaining = vec![];
+            for ((photo, aspect), rect) in photos.into_iter().zip(layout.into_iter()) {
+                if let Media::Photo {
+                    ref path,
+                    orientation,
+                    ..
+                } = photo
+                {
+                    let pixbuf = MediaProvider::load_pixbuf_at_size(
+                        path,
+                        orientation,
+                        rect.width,
+                        rect.height,
+                    );
+                    if let Some(pixbuf) = pixbuf {
+                        remaining.push((photo.clone(), aspect));
+                        loaded.push((photo, pixbuf, rect));
                     }
                 }
+            }
 
-                let date_time = match exif_obj.get_field(Tag::DateTime, In::PRIMARY) {
-                    Some(date_time) => match date_time.value {
-                        Value::Ascii(ref v) if !v.is_empty() => Some(v),
-                        _ => None,
-                    },
-                    None => None,
-                };
+            if loaded.len() == count {
+                break loaded;
+            }
+            debug!("Laying out the collage again without photos that failed to load");
+            photos = remaining;
+        };
 
-                let mut string_date_time: Option<String> = None;
+        let background = Background::for_photo(config.background_fill, &loaded[0].1, screen_size);
+        let location = loaded.iter().find_map(|(photo, _, _)| match photo {
+            Media::Photo { location, .. } => *location,
+            _ => None,
+        });
+        let address = MediaProvider::geocode(config, geocoder, location);
+        let mut minimap = MediaProvider::minimap(config, tiles, location);
 
-                if let Some(ascii_date_time) = date_time {
-                    let date_time = DateTime::from_ascii(&ascii_date_time[0]);
-                    if date_time.is_ok() {
-                        string_date_time = Some(date_time.unwrap().to_string());
-                        debug!(string_date_time, "Found time");
-                    }
+        let mut photos = vec![];
+        let mut photo_data = vec![];
+        let mut layout = vec![];
+        for (photo, pixbuf, rect) in loaded {
+            photos.push(photo);
+            photo_data.push(PhotoData {
+                pixbuf,
+                background: Background::None,
+                energy: None,
+                // The window shows the first map it finds
+                minimap: minimap.take(),
+            });
+            layout.push(rect);
+        }
+        photo_data[0].background = background;
+
+        Some(MediaMessage::Collage {
+            photos,
+            photo_data,
+            layout,
+            address,
+        })
+    }
+
+    fn geocode(
+        config: &Config,
+        geocoder: &Geocoder,
+        location: Option<(f32, f32)>,
+    ) -> Result<String, String> {
+        let mut address_message = Err("Not set".into());
+        if config.reverse_geocode {
+            if let Some(location) = location {
+                debug!("Geolocating");
+                let address = geocoder.reverse_geocode(location.0, location.1);
+                if let Err(e) = &address {
+                    events::emit_error(format!("Failed to get reverse geocode response, {}", e));
                 }
+                address_message = address;
+                debug!("Finished geolocating");
+            }
+        }
 
-                return Ok(Some(Media::Photo {
-                    path: random_media_path_clone,
-                    orientation,
-                    location,
-                    date: string_date_time,
-                }));
-            } else {
-                return Ok(Some(Media::Video {
-                    path: random_media_path,
-                }));
+        address_message
+    }
+
+    fn minimap(
+        config: &Config,
+        tiles: &Option<TileSource>,
+        location: Option<(f32, f32)>,
+    ) -> Option<Arc<UnsafeSendSync<Pixbuf>>> {
+        let (tiles, location) = (tiles.as_ref()?, location?);
+        debug!("Rendering map");
+        render_minimap(tiles, location, config.minimap_zoom, config.minimap_size)
+            .map(|pixbuf| Arc::new(UnsafeSendSync::new(pixbuf)))
+    }
+
+    fn timestamp(photo: &Media) -> Option<i64> {
+        match photo {
+            Media::Photo {
+                date: Some(date), ..
+            } => date.timestamp(),
+            _ => None,
+        }
+    }
+
+    /// Reads the EXIF metadata of a photo. Captions are only read for photos
+    /// that are shown, not while looking through candidates.
+    fn read_photo(path: PathBuf, caption: bool) -> Result<Media, io::Error> {
+        let exifreader = exif::Reader::new();
+        let file = std::fs::File::open(&path)?;
+        let mut bufreader = std::io::BufReader::new(file);
+        let exif = exifreader
+            .read_from_container(&mut bufreader)
+            .map_err(|e| io::Error::new(io::ErrorKind::Other, e));
+
+        if exif.is_err() {
+            debug!("No exif data");
+            let date = CaptureDate::from_path(&path);
+            let caption = caption.then(|| read_caption(&path, None)).flatten();
+            return Ok(Media::Photo {
+                path,
+                orientation: 0,
+                location: None,
+                date,
+                caption,
+                camera: None,
+            });
+        }
+
+        let exif_obj = exif.unwrap();
+
+        let orientation = match exif_obj.get_field(Tag::Orientation, In::PRIMARY) {
+            Some(orientation) => match orientation.value.get_uint(0) {
+                Some(v @ 1..=8) => v,
+                _ => 1,
+            },
+            None => 1,
+        };
+        debug!(orientation, "Found orientation");
+
+        let latitude = gps_degrees(&exif_obj, Tag::GPSLatitude, Tag::GPSLatitudeRef);
+        debug!(?latitude, "Found latitude");
+        let longitude = gps_degrees(&exif_obj, Tag::GPSLongitude, Tag::GPSLongitudeRef);
+        debug!(?longitude, "Found longitude");
+        let location = latitude.zip(longitude);
+
+        let date = CaptureDate::from_exif(&exif_obj).or_else(|| CaptureDate::from_path(&path));
+        debug!(?date, "Found time");
+
+        let caption = caption
+            .then(|| read_caption(&path, Some(&exif_obj)))
+            .flatten();
+        debug!(?caption, "Found caption");
+
+        let camera = CameraInfo::from_exif(&exif_obj);
+
+        Ok(Media::Photo {
+            path,
+            orientation,
+            location,
+            date,
+            caption,
+            camera,
+        })
+    }
+
+    /// The other photos in the folder of `path` that can be shown, in random
+    /// order.
+    fn folder_candidates(&self, path: &Path) -> Vec<PathBuf> {
+        let Some(Ok(entries)) = path.parent().map(fs::read_dir) else {
+            return vec![];
+        };
+        let mut candidates: Vec<PathBuf> = entries
+            .filter_map(|entry| entry.ok())
+            .map(|entry| entry.path())
+            .filter(|candidate| candidate != path && self.is_valid_photo(candidate))
+            .collect();
+        candidates.shuffle(&mut rand::thread_rng());
+        candidates
+    }
+
+    /// Looks for a portrait photo among `candidates`, preferring one taken on
+    /// the same day.
+    fn find_portrait_partner(
+        candidates: Vec<PathBuf>,
+        date: &Option<CaptureDate>,
+    ) -> Option<Media> {
+        let mut fallback = None;
+        for candidate in candidates.into_iter().take(PAIRING_CANDIDATES) {
+            let Ok(photo) = MediaProvider::read_photo(candidate, false) else {
+                continue;
+            };
+            let Media::Photo {
+                path: ref candidate_path,
+                orientation,
+                date: ref candidate_date,
+                ..
+            } = photo
+            else {
+                continue;
+            };
+
+            if !MediaProvider::is_portrait(candidate_path, orientation) {
+                continue;
+            }
+
+            if let (Some(date), Some(candidate_date)) = (date, candidate_date) {
+                if date.same_day(candidate_date) {
+                    debug!(partner = candidate_path.to_str(), "Found same day partner");
+                    return Some(MediaProvider::with_caption(photo));
+                }
+            }
+
+            if fallback.is_none() {
+                fallback = Some(photo);
             }
         }
 
-        return Err(io::Error::new(
-            io::ErrorKind::NotFound,
-            "No valid photo found",
-        ));
+        fallback.map(MediaProvider::with_caption)
+    }
+
+    /// Reads a photo found while looking through candidates again, with its
+    /// caption.
+    fn with_caption(photo: Media) -> Media {
+        match &photo {
+            Media::Photo { path, .. } => {
+                MediaProvider::read_photo(path.clone(), true).unwrap_or(photo)
+            }
+            Media::Video { .. } => photo,
+        }
+    }
+
+    fn is_valid_photo(&self, path: &Path) -> bool {
+        let valid_extension = path
+            .extension()
+            .and_then(|e| e.to_str())
+            .is_some_and(|e| self.photo_valid_extensions.contains(&e.to_lowercase()));
+
+        valid_extension
+            && path.is_file()
+            && !self.collections.is_hidden(path)
+            && !self
+                .failed_files
+                .failed
+                .iter()
+                .any(|f| Some(f.as_str()) == path.to_str())
+    }
+
+    fn is_portrait(path: &Path, orientation: u32) -> bool {
+        MediaProvider::aspect_ratio(path, orientation).is_some_and(|aspect| aspect < 1.0)
+    }
+
+    /// Width / height as displayed. Uses the image header and the EXIF
+    /// orientation, so the photo doesn't need to be decoded.
+    fn aspect_ratio(path: &Path, orientation: u32) -> Option<f64> {
+        let (_, width, height) = Pixbuf::file_info(path)?;
+        if width <= 0 || height <= 0 {
+            return None;
+        }
+
+        match orientation {
+            5..=8 => Some(height as f64 / width as f64),
+            _ => Some(width as f64 / height as f64),
+        }
+    }
+
+    fn load_pixbuf(path: &Path, orientation: u32) -> Option<Arc<UnsafeSendSync<Pixbuf>>> {
+        MediaProvider::check_pixbuf(path, Pixbuf::from_file(path), orientation)
+    }
+
+    /// Decodes the photo straight at the size it will be shown at. `width`
+    /// and `height` are given for the rotated photo.
+    fn load_pixbuf_at_size(
+        path: &Path,
+        orientation: u32,
+        width: i32,
+        height: i32,
+    ) -> Option<Arc<UnsafeSendSync<Pixbuf>>> {
+        let (width, height) = match orientation {
+            5..=8 => (height, width),
+            _ => (width, height),
+        };
+        let image_data = Pixbuf::from_file_at_scale(path, width, height, false);
+        MediaProvider::check_pixbuf(path, image_data, orientation)
+    }
+
+    fn check_pixbuf(
+        path: &Path,
+        image_data: Result<Pixbuf, gtk::glib::Error>,
+        orientation: u32,
+    ) -> Option<Arc<UnsafeSendSync<Pixbuf>>> {
+        if let Err(err) = image_data {
+            warn!("Loading image failed {:?}", err);
+            events::emit_error(format!("Loading {:?} failed, {}", path, err));
+            return None;
+        }
+
+        let pixbuf = Arc::new(UnsafeSendSync::new(image_data.unwrap()));
+
+        if pixbuf.height() <= 0 || pixbuf.width() <= 0 {
+            warn!("Corrupted image {:?}", path);
+            events::emit_error(format!("Corrupted image {:?}", path));
+            return None;
+        }
+
+        Some(MediaProvider::rotate_photo(pixbuf, orientation))
+    }
+
+    /// Reads the photo or video at `path`.
+    fn media_at(&self, path: PathBuf) -> Result<Media, io::Error> {
+        if !path.is_file() {
+            return Err(io::Error::new(
+                io::ErrorKind::NotFound,
+                format!("{:?} not found", path),
+            ));
+        }
+
+        let extension = path
+            .extension()
+            .and_then(|e| e.to_str())
+            .map(|e| e.to_lowercase())
+            .unwrap_or_default();
+        if self.photo_valid_extensions.contains(&extension) {
+            MediaProvider::read_photo(path, true)
+        } else if self.video_valid_extensions.contains(&extension) {
+            Ok(Media::Video { path })
+        } else {
+            Err(io::Error::new(
+                io::ErrorKind::InvalidInput,
+                format!("{:?} is not a photo or video", path),
+            ))
+        }
+    }
+
+    /// The configured path to pick from, a random one unless an album is
+    /// selected.
+    fn album_index(&self, rng: &mut impl Rng) -> usize {
+        match self.album {
+            Some(Album::Path(index)) if index < self.config.paths.len() => index,
+            _ => rng.gen_range(0..self.config.paths.len()),
+        }
+    }
+
+    fn remember(&mut self, media: &Media) {
+        self.history.push(media.path().to_path_buf());
+        if self.history.len() > HISTORY_SIZE {
+            self.history.remove(0);
+        }
+    }
+
+    fn request(&mut self, request: MediaRequest) {
+        self.requested = Some(request);
+        self.wake();
+    }
+
+    fn wake(&self) {
+        if let Some(wake) = &self.wake {
+            let _ = wake.send(());
+        }
+    }
+
+    /// Seconds until the next photo, counted from now.
+    pub fn set_interval(&mut self, seconds: u32) {
+        self.interval = seconds;
+        self.wake();
+    }
+
+    pub fn next(&mut self) {
+        self.request(MediaRequest::Next);
+    }
+
+    /// Uses a reloaded config for the next photos. Resets the interval, and
+    /// the album when its path is gone.
+    pub fn set_config(&mut self, config: Config) {
+        if let Some(Album::Path(index)) = self.album {
+            if self.config.paths.get(index) != config.paths.get(index) {
+                self.album = None;
+            }
+        }
+        self.interval = config.transition_time;
+        self.config = config;
+    }
+
+    pub fn previous(&mut self) {
+        // The current photo is the last one
+        if self.history.len() < 2 {
+            return;
+        }
+        self.history.pop();
+        if let Some(path) = self.history.pop() {
+            self.request(MediaRequest::Show(path));
+        }
+    }
+
+    /// Shows a photo from the configured paths or the upload folder.
+    pub fn show(&mut self, path: PathBuf) -> Result<(), String> {
+        let path = allowed_path(&self.config, &path)
+            .ok_or_else(|| format!("{:?} is not in the photo paths", path))?;
+        self.request(MediaRequest::Show(path));
+        Ok(())
+    }
+
+    /// Selects one of the configured paths, by path or folder name, or the
+    /// favorites. `None` goes back to all paths.
+    pub fn set_album(&mut self, name: Option<&str>) -> Result<(), String> {
+        self.album = match name {
+            None | Some("") | Some(ALL_ALBUM) => None,
+            Some(FAVORITES_ALBUM) => Some(Album::Favorites),
+            Some(name) => {
+                let index = self
+                    .config
+                    .paths
+                    .iter()
+                    .position(|path| path == name || album_name(path) == name)
+                    .ok_or_else(|| format!("unknown album {}", name))?;
+                Some(Album::Path(index))
+            }
+        };
+
+        self.next();
+        Ok(())
+    }
+
+    /// The selected album as accepted by `set_album`, `None` for all paths.
+    pub fn album_name(&self) -> Option<String> {
+        match self.album? {
+            Album::Favorites => Some(FAVORITES_ALBUM.to_string()),
+            Album::Path(index) => self
+                .config
+                .paths
+                .get(index)
+                .map(|path| album_name(path).to_string()),
+        }
+    }
+
+    /// The canonical path of the given photo, or of the one shown now, as
+    /// stored in the collections. Only files under the photo paths or the
+    /// upload folder are accepted, as for `show`.
+    fn photo_or_current(&self, path: Option<PathBuf>) -> Result<String, String> {
+        let path = path
+            .or_else(|| self.history.last().cloned())
+            .ok_or_else(|| "no photo is shown".to_string())?;
+        let path = allowed_path(&self.config, &path)
+            .filter(|path| path.is_file())
+            .ok_or_else(|| format!("{:?} is not a file in the photo paths", path))?;
+        path.to_str()
+            .map(|path| path.to_string())
+            .ok_or_else(|| format!("invalid path {:?}", path))
+    }
+
+    pub fn favorite(&mut self, path: Option<PathBuf>) -> Result<(), String> {
+        let path = self.photo_or_current(path)?;
+        if !self.collections.favorites.contains(&path) {
+            self.collections.favorites.push(path);
+            write_collections(&self.collections).map_err(|e| e.to_string())?;
+        }
+        Ok(())
+    }
+
+    /// Never shows the photo again. Skips to the next one when it is the
+    /// current photo.
+    pub fn hide(&mut self, path: Option<PathBuf>) -> Result<(), String> {
+        let path = self.photo_or_current(path)?;
+        let current = self
+            .history
+            .last()
+            .and_then(|current| allowed_path(&self.config, current))
+            .is_some_and(|current| current.to_str() == Some(path.as_str()));
+        if !self.collections.hidden.contains(&path) {
+            self.collections.hidden.push(path);
+            write_collections(&self.collections).map_err(|e| e.to_string())?;
+        }
+        if current {
+            self.history.pop();
+            self.next();
+        }
+        Ok(())
+    }
+
+    /// Takes the given photo, or the one shown now, out of the favorites.
+    pub fn unfavorite(&mut self, path: Option<PathBuf>) -> Result<(), String> {
+        let path = path
+            .or_else(|| self.history.last().cloned())
+            .ok_or_else(|| "no photo is shown".to_string())?;
+        if remove_entry(&mut self.collections.favorites, &path) {
+            write_collections(&self.collections).map_err(|e| e.to_string())?;
+        }
+        Ok(())
+    }
+
+    /// Shows a hidden photo again.
+    pub fn unhide(&mut self, path: PathBuf) -> Result<(), String> {
+        if remove_entry(&mut self.collections.hidden, &path) {
+            write_collections(&self.collections).map_err(|e| e.to_string())?;
+        }
+        Ok(())
     }
 
     pub fn remove_current_failed_photo(&mut self) {
@@ -387,7 +1552,7 @@ impl MediaProvider {
             debug!(current_entry = entry.path().to_str(), "Trying an entry");
             if entry.path().is_dir() {
                 if let Some(dir) = fs::read_dir(entry.path()).ok() {
-                    println!("Dir found, recursing: {:?}", dir);
+                    debug!("Dir found, recursing: {:?}", dir);
                     return MediaProvider::get_random_entry(dir, valid_extensions);
                 }
             }
@@ -401,7 +1566,7 @@ impl MediaProvider {
                 }
             }
 
-            println!("Invalid extension: {:?}", entry.path());
+            debug!("Invalid extension: {:?}", entry.path());
         }
 
         Err(io::Error::new(
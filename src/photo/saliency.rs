@@ -0,0 +1,116 @@
+use gtk::gdk_pixbuf::{InterpType, Pixbuf};
+
+use super::background::rgb_pixels;
+
+/// Luminance and edge energy of a downscaled copy of a photo. Edges are a
+/// cheap stand-in for "interesting" content such as faces and text.
+pub struct EnergyMap {
+    pub width: usize,
+    pub height: usize,
+    /// 0.0 - 1.0 per pixel
+    pub luminance: Vec<f32>,
+    /// Gradient magnitude per pixel, 0.0 - 2.0
+    pub energy: Vec<f32>,
+}
+
+impl EnergyMap {
+    /// Builds the map from a copy of the photo whose longest side is
+    /// `max_side` pixels.
+    pub fn from_pixbuf(pixbuf: &Pixbuf, max_side: i32) -> Option<Self> {
+        let (width, height) = (pixbuf.width(), pixbuf.height());
+        if width <= 0 || height <= 0 {
+            return None;
+        }
+
+        let scale = max_side as f64 / width.max(height) as f64;
+        let (small_width, small_height) = (
+            ((width as f64 * scale) as i32).max(2),
+            ((height as f64 * scale) as i32).max(2),
+        );
+        let small = pixbuf.scale_simple(small_width, small_height, InterpType::Bilinear)?;
+
+        Some(EnergyMap::from_rgb(
+            &rgb_pixels(&small),
+            small_width as usize,
+            small_height as usize,
+        ))
+    }
+
+    pub fn from_rgb(pixels: &[u8], width: usize, height: usize) -> Self {
+        let luminance: Vec<f32> = pixels
+            .chunks_exact(3)
+            .map(|p| (0.299 * p[0] as f32 + 0.587 * p[1] as f32 + 0.114 * p[2] as f32) / 255.0)
+            .collect();
+
+        let mut energy = vec![0.0; width * height];
+        for y in 0..height {
+            for x in 0..width {
+                let at = |x: usize, y: usize| luminance[y * width + x];
+                let dx = at((x + 1).min(width - 1), y) - at(x.saturating_sub(1), y);
+                let dy = at(x, (y + 1).min(height - 1)) - at(x, y.saturating_sub(1));
+                energy[y * width + x] = dx.abs() + dy.abs();
+            }
+        }
+
+        EnergyMap {
+            width,
+            height,
+            luminance,
+            energy,
+        }
+    }
+
+    /// Total energy of every column (`horizontal`) or row.
+    pub fn profile(&self, horizontal: bool) -> Vec<f64> {
+        let mut profile = vec![0.0; if horizontal { self.width } else { self.height }];
+        for y in 0..self.height {
+            for x in 0..self.width {
+                let i = if horizontal { x } else { y };
+                profile[i] += self.energy[y * self.width + x] as f64;
+            }
+        }
+        profile
+    }
+
+    /// Mean luminance and mean energy of a region given in fractions of the
+    /// photo size.
+    pub fn region_stats(&self, x: f64, y: f64, width: f64, height: f64) -> (f32, f32) {
+        let x0 = ((x * self.width as f64) as usize).min(self.width - 1);
+        let y0 = ((y * self.height as f64) as usize).min(self.height - 1);
+        let x1 = (((x + width) * self.width as f64).ceil() as usize).clamp(x0 + 1, self.width);
+        let y1 = (((y + height) * self.height as f64).ceil() as usize).clamp(y0 + 1, self.height);
+
+        let mut luminance = 0.0;
+        let mut energy = 0.0;
+        for row in y0..y1 {
+            for col in x0..x1 {
+                luminance += self.luminance[row * self.width + col];
+                energy += self.energy[row * self.width + col];
+            }
+        }
+
+        let count = ((x1 - x0) * (y1 - y0)) as f32;
+        (luminance / count, energy / count)
+    }
+}
+
+/// Start of the `window` long span of `profile` with the highest total. Ties
+/// go to the span closest to the middle.
+pub fn best_window(profile: &[f64], window: usize) -> usize {
+    if window >= profile.len() {
+        return 0;
+    }
+
+    let center = (profile.len() - window) as f64 / 2.0;
+    let mut sum: f64 = profile[..window].iter().sum();
+    let mut best = (sum, 0);
+    for start in 1..=profile.len() - window {
+        sum += profile[start + window - 1] - profile[start - 1];
+        let closer = (start as f64 - center).abs() < (best.1 as f64 - center).abs();
+        if sum > best.0 + f64::EPSILON || ((sum - best.0).abs() <= f64::EPSILON && closer) {
+            best = (sum, start);
+        }
+    }
+
+    best.1
+}
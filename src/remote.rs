@@ -0,0 +1,242 @@
+use std::path::PathBuf;
+
+use serde::Deserialize;
+
+/// Commands from MQTT and other remote controls, handled on the main thread.
+#[derive(Debug, Clone, PartialEq)]
+pub enum RemoteCommand {
+    SetPaused(bool),
+    /// Overrides the schedule and sensors until they next change
+    SetDisplay(bool),
+    ToggleCameraInfo,
+    Next,
+    Previous,
+    /// Only show photos from one of the configured paths, or from the
+    /// favorites. `None` goes back to all of them.
+    Album(Option<String>),
+    /// Seconds between photos
+    Interval(u32),
+    /// From 0.0 to 1.0
+    Brightness(f64),
+    Show(PathBuf),
+    /// The current photo when no path is given
+    Favorite(Option<PathBuf>),
+    /// The current photo when no path is given
+    Unfavorite(Option<PathBuf>),
+    /// The current photo when no path is given
+    Hide(Option<PathBuf>),
+    Unhide(PathBuf),
+    /// Reads the config file again
+    ReloadConfig,
+}
+
+/// JSON payload of a command. Only the field the command needs is read.
+#[derive(Deserialize, Default)]
+struct Payload {
+    album: Option<String>,
+    seconds: Option<u32>,
+    level: Option<f64>,
+    path: Option<String>,
+}
+
+impl RemoteCommand {
+    /// Parses a command by name, e.g. the last part of `<base>/cmd/interval`.
+    /// The payload is JSON like `{"seconds": 30}`, or just the value, like
+    /// `30`.
+    pub fn parse(name: &str, payload: &[u8]) -> Result<Self, String> {
+        let text = String::from_utf8_lossy(payload).trim().to_string();
+        let (json, value) = if text.starts_with('{') {
+            let json: Payload = json5::from_str(&text)
+                .map_err(|e| format!("invalid payload for {}, {}", name, e))?;
+            (json, None)
+        } else {
+            (
+                Payload::default(),
+                Some(text).filter(|text| !text.is_empty()),
+            )
+        };
+
+        match name {
+            "next" => Ok(RemoteCommand::Next),
+            "prev" | "previous" => Ok(RemoteCommand::Previous),
+            "pause" => Ok(RemoteCommand::SetPaused(true)),
+            "resume" => Ok(RemoteCommand::SetPaused(false)),
+            "paused" => value
+                .as_deref()
+                .and_then(parse_switch)
+                .map(RemoteCommand::SetPaused)
+                .ok_or_else(|| "paused needs ON or OFF".to_string()),
+            "display" => value
+                .as_deref()
+                .and_then(parse_switch)
+                .map(RemoteCommand::SetDisplay)
+                .ok_or_else(|| "display needs ON or OFF".to_string()),
+            "info" => Ok(RemoteCommand::ToggleCameraInfo),
+            "album" => Ok(RemoteCommand::Album(json.album.or(value))),
+            "interval" => json
+                .seconds
+                .or_else(|| value?.parse().ok())
+                .filter(|seconds| *seconds > 0)
+                .map(RemoteCommand::Interval)
+                .ok_or_else(|| "interval needs a number of seconds".to_string()),
+            "brightness" => json
+                .level
+                .or_else(|| value?.parse().ok())
+                .filter(|level: &f64| (0.0..=1.0).contains(level))
+                .map(RemoteCommand::Brightness)
+                .ok_or_else(|| "brightness needs a level from 0.0 to 1.0".to_string()),
+            "show" => json
+                .path
+                .or(value)
+                .map(|path| RemoteCommand::Show(PathBuf::from(path)))
+                .ok_or_else(|| "show needs a path".to_string()),
+            "favorite" => Ok(RemoteCommand::Favorite(
+                json.path.or(value).map(PathBuf::from),
+            )),
+            "unfavorite" => Ok(RemoteCommand::Unfavorite(
+                json.path.or(value).map(PathBuf::from),
+            )),
+            "hide" => Ok(RemoteCommand::Hide(json.path.or(value).map(PathBuf::from))),
+            "unhide" => json
+                .path
+                .or(value)
+                .map(|path| RemoteCommand::Unhide(PathBuf::from(path)))
+                .ok_or_else(|| "unhide needs a path".to_string()),
+            "reload" => Ok(RemoteCommand::ReloadConfig),
+            _ => Err(format!("unknown command {}", name)),
+        }
+    }
+}
+
+/// On or off, like Home Assistant switches send them.
+fn parse_switch(value: &str) -> Option<bool> {
+    match value.to_lowercase().as_str() {
+        "on" | "1" | "true" => Some(true),
+        "off" | "0" | "false" => Some(false),
+        _ => None,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn parse(name: &str, payload: &str) -> Result<RemoteCommand, String> {
+        RemoteCommand::parse(name, payload.as_bytes())
+    }
+
+    #[test]
+    fn parses_commands_without_payload() {
+        assert_eq!(parse("next", ""), Ok(RemoteCommand::Next));
+        assert_eq!(parse("prev", ""), Ok(RemoteCommand::Previous));
+        assert_eq!(parse("previous", ""), Ok(RemoteCommand::Previous));
+        assert_eq!(parse("pause", ""), Ok(RemoteCommand::SetPaused(true)));
+        assert_eq!(parse("resume", ""), Ok(RemoteCommand::SetPaused(false)));
+        assert_eq!(parse("info", ""), Ok(RemoteCommand::ToggleCameraInfo));
+        assert_eq!(parse("reload", ""), Ok(RemoteCommand::ReloadConfig));
+        // The payload of commands that don't need one is ignored
+        assert_eq!(parse("next", "PRESS"), Ok(RemoteCommand::Next));
+    }
+
+    #[test]
+    fn parses_switches() {
+        assert_eq!(parse("paused", "ON"), Ok(RemoteCommand::SetPaused(true)));
+        assert_eq!(parse("paused", "off"), Ok(RemoteCommand::SetPaused(false)));
+        assert_eq!(parse("display", "1"), Ok(RemoteCommand::SetDisplay(true)));
+        assert_eq!(
+            parse("display", " false\n"),
+            Ok(RemoteCommand::SetDisplay(false))
+        );
+        assert!(parse("display", "dim").is_err());
+        assert!(parse("display", "").is_err());
+        assert!(parse("paused", r#"{"state": "ON"}"#).is_err());
+    }
+
+    #[test]
+    fn parses_json_and_bare_values() {
+        assert_eq!(
+            parse("album", r#"{"album": "2019"}"#),
+            Ok(RemoteCommand::Album(Some("2019".to_string())))
+        );
+        assert_eq!(
+            parse("album", "favorites"),
+            Ok(RemoteCommand::Album(Some("favorites".to_string())))
+        );
+        assert_eq!(parse("album", ""), Ok(RemoteCommand::Album(None)));
+        assert_eq!(parse("album", "{}"), Ok(RemoteCommand::Album(None)));
+
+        assert_eq!(
+            parse("interval", r#"{"seconds": 30}"#),
+            Ok(RemoteCommand::Interval(30))
+        );
+        assert_eq!(parse("interval", "45"), Ok(RemoteCommand::Interval(45)));
+        assert_eq!(
+            parse("interval", "{seconds: 10}"),
+            Ok(RemoteCommand::Interval(10))
+        );
+
+        assert_eq!(
+            parse("brightness", r#"{"level": 0.5}"#),
+            Ok(RemoteCommand::Brightness(0.5))
+        );
+        assert_eq!(parse("brightness", "1"), Ok(RemoteCommand::Brightness(1.0)));
+        assert_eq!(
+            parse("brightness", "0.0"),
+            Ok(RemoteCommand::Brightness(0.0))
+        );
+
+        assert_eq!(
+            parse("show", r#"{"path": "/photos/a.jpg"}"#),
+            Ok(RemoteCommand::Show(PathBuf::from("/photos/a.jpg")))
+        );
+        assert_eq!(
+            parse("show", "/photos/b.jpg"),
+            Ok(RemoteCommand::Show(PathBuf::from("/photos/b.jpg")))
+        );
+    }
+
+    #[test]
+    fn favorite_and_hide_default_to_the_current_photo() {
+        assert_eq!(parse("favorite", ""), Ok(RemoteCommand::Favorite(None)));
+        assert_eq!(parse("hide", "{}"), Ok(RemoteCommand::Hide(None)));
+        assert_eq!(
+            parse("favorite", r#"{"path": "/photos/a.jpg"}"#),
+            Ok(RemoteCommand::Favorite(Some(PathBuf::from(
+                "/photos/a.jpg"
+            ))))
+        );
+        assert_eq!(
+            parse("hide", "/photos/a.jpg"),
+            Ok(RemoteCommand::Hide(Some(PathBuf::from("/photos/a.jpg"))))
+        );
+        assert_eq!(parse("unfavorite", ""), Ok(RemoteCommand::Unfavorite(None)));
+        assert_eq!(
+            parse("unhide", r#"{"path": "/photos/a.jpg"}"#),
+            Ok(RemoteCommand::Unhide(PathBuf::from("/photos/a.jpg")))
+        );
+        assert!(parse("unhide", "").is_err());
+    }
+
+    #[test]
+    fn rejects_out_of_range_values() {
+        assert!(parse("interval", "0").is_err());
+        assert!(parse("interval", "-5").is_err());
+        assert!(parse("interval", "soon").is_err());
+        assert!(parse("interval", r#"{"seconds": 0}"#).is_err());
+        assert!(parse("interval", "").is_err());
+        assert!(parse("brightness", "1.5").is_err());
+        assert!(parse("brightness", "-0.1").is_err());
+        assert!(parse("brightness", r#"{"level": 2}"#).is_err());
+        assert!(parse("brightness", "NaN").is_err());
+        assert!(parse("show", "").is_err());
+        assert!(parse("show", "{}").is_err());
+    }
+
+    #[test]
+    fn rejects_unknown_commands_and_invalid_json() {
+        assert!(parse("reboot", "").is_err());
+        assert!(parse("NEXT", "").is_err());
+        assert!(parse("interval", r#"{"seconds": "#).is_err());
+        assert!(parse("interval", r#"{"seconds": "thirty"}"#).is_err());
+    }
+}
@@ -0,0 +1,97 @@
+use once_cell::sync::Lazy;
+use serde::Serialize;
+use tokio::sync::watch;
+use tracing::warn;
+
+use crate::mqtt::MqttPublisher;
+
+/// What the frame is doing, for anything reporting it outside the window.
+#[derive(Serialize, Default, Debug, Clone, PartialEq)]
+pub struct FrameState {
+    pub paused: bool,
+    pub display_on: bool,
+    /// Path of the photo or video shown, the first one of a pair or collage
+    pub photo: Option<String>,
+    /// As shown on the overlay
+    pub date: Option<String>,
+    pub location: Option<String>,
+    /// Captions and camera details, one line per photo
+    pub caption: Option<String>,
+    pub camera: Option<String>,
+    /// `None` when showing photos from all paths
+    pub album: Option<String>,
+    /// Seconds between photos
+    pub interval: u32,
+}
+
+impl FrameState {
+    /// Values published on `<base>/state/<name>`.
+    fn topics(&self) -> Vec<(&'static str, String)> {
+        vec![
+            (
+                "playback",
+                if self.paused { "paused" } else { "playing" }.to_string(),
+            ),
+            (
+                "display",
+                if self.display_on { "on" } else { "off" }.to_string(),
+            ),
+            ("photo", self.photo.clone().unwrap_or_default()),
+            ("date", self.date.clone().unwrap_or_default()),
+            ("location", self.location.clone().unwrap_or_default()),
+            ("album", self.album.clone().unwrap_or_default()),
+            ("interval", self.interval.to_string()),
+        ]
+    }
+}
+
+static STATE: Lazy<watch::Sender<FrameState>> =
+    Lazy::new(|| watch::channel(FrameState::default()).0);
+
+/// Changes the state. Subscribers only hear about real changes.
+pub fn update_state(modify: impl FnOnce(&mut FrameState)) {
+    STATE.send_if_modified(|state| {
+        let before = state.clone();
+        modify(state);
+        *state != before
+    });
+}
+
+pub fn current_state() -> FrameState {
+    STATE.borrow().clone()
+}
+
+pub fn subscribe() -> watch::Receiver<FrameState> {
+    STATE.subscribe()
+}
+
+/// Publishes the state as retained messages on `<base>/state/<name>`, only
+/// the values that changed, and all of it as JSON on `<base>/state`.
+pub async fn publish_to_mqtt(publisher: MqttPublisher, base_topic: String) {
+    let mut receiver = subscribe();
+    let mut published: Vec<(&'static str, String)> = vec![];
+
+    loop {
+        let state = receiver.borrow_and_update().clone();
+        let topics = state.topics();
+        for (name, value) in &topics {
+            if !published.contains(&(*name, value.clone())) {
+                publisher.publish(
+                    format!("{}/state/{}", base_topic, name),
+                    value.clone(),
+                    true,
+                );
+            }
+        }
+        published = topics;
+
+        match serde_json::to_string(&state) {
+            Ok(json) => publisher.publish(format!("{}/state", base_topic), json, true),
+            Err(e) => warn!("Failed to serialize state, {}", e),
+        }
+
+        if receiver.changed().await.is_err() {
+            break;
+        }
+    }
+}
@@ -0,0 +1,195 @@
+use std::sync::Arc;
+use std::time::{Duration, Instant};
+
+use async_trait::async_trait;
+use gtk::glib;
+use serde::Deserialize;
+use tracing::{debug, warn};
+
+use crate::gui::overlay::OverlayWidget;
+use crate::photo::provider::Config;
+
+mod mqtt;
+mod openweather;
+
+pub use mqtt::MqttWeather;
+pub use openweather::OpenWeather;
+
+/// How often the cached weather is checked for staleness, and the shortest
+/// time between fetches.
+const STALE_CHECK_INTERVAL: Duration = Duration::from_secs(60);
+/// A fetch that takes longer counts as failed, so that the stale check
+/// still runs when a server hangs.
+const FETCH_TIMEOUT: Duration = Duration::from_secs(30);
+
+#[derive(Deserialize, Debug, Clone, Copy, PartialEq)]
+#[serde(rename_all = "lowercase")]
+pub enum WeatherSource {
+    /// OpenWeather compatible HTTP API
+    OpenWeather,
+    /// JSON or a plain temperature published on `weather_mqtt_topic`
+    Mqtt,
+}
+
+#[derive(Deserialize, Default, Debug, Clone, Copy, PartialEq)]
+#[serde(rename_all = "lowercase")]
+pub enum WeatherUnits {
+    #[default]
+    Metric,
+    Imperial,
+}
+
+#[derive(Deserialize, Debug, Clone, PartialEq)]
+pub struct Weather {
+    pub temperature: f64,
+    #[serde(default)]
+    pub condition: Option<String>,
+    #[serde(default)]
+    pub forecast: Vec<Forecast>,
+    /// Unix time of the observation
+    #[serde(default)]
+    pub observed: i64,
+}
+
+#[derive(Deserialize, Debug, Clone, PartialEq)]
+pub struct Forecast {
+    /// Shown as is, e.g. "15:00" or "Tue"
+    pub time: String,
+    pub temperature: f64,
+    #[serde(default)]
+    pub condition: Option<String>,
+}
+
+impl Weather {
+    /// "21° Cloudy"
+    pub fn current(&self) -> String {
+        match &self.condition {
+            Some(condition) => format!("{:.0}° {}", self.temperature, condition),
+            None => format!("{:.0}°", self.temperature),
+        }
+    }
+
+    /// One line per forecast entry, at most `count` of them.
+    pub fn forecast(&self, count: usize) -> String {
+        self.forecast
+            .iter()
+            .take(count)
+            .map(|forecast| match &forecast.condition {
+                Some(condition) => {
+                    format!(
+                        "{}  {:.0}° {}",
+                        forecast.time, forecast.temperature, condition
+                    )
+                }
+                None => format!("{}  {:.0}°", forecast.time, forecast.temperature),
+            })
+            .collect::<Vec<String>>()
+            .join("\n")
+    }
+
+    fn age(&self) -> Duration {
+        let now = glib::real_time() / 1_000_000;
+        Duration::from_secs(now.saturating_sub(self.observed).max(0) as u64)
+    }
+}
+
+#[async_trait]
+pub trait WeatherProvider: Send + Sync {
+    async fn fetch(&self) -> Result<Weather, String>;
+
+    /// Time between fetches.
+    fn refresh_interval(&self) -> Duration;
+
+    /// Topic the provider wants to receive MQTT messages from.
+    fn mqtt_topic(&self) -> Option<&str> {
+        None
+    }
+
+    fn handle_message(&self, _payload: &[u8]) {}
+
+    /// Completes when new weather can be fetched before the next refresh,
+    /// e.g. after an MQTT message. Never completes by default.
+    async fn updated(&self) {
+        std::future::pending().await
+    }
+}
+
+/// Builds the configured provider. Returns `None` when the weather overlay
+/// or the provider is not configured.
+pub fn create_provider(config: &Config) -> Option<Arc<dyn WeatherProvider>> {
+    if !config
+        .overlays
+        .iter()
+        .any(|overlay| overlay.widget == OverlayWidget::Weather)
+    {
+        return None;
+    }
+
+    let refresh = Duration::from_secs(config.weather_refresh_minutes as u64 * 60);
+    match config.weather_provider? {
+        WeatherSource::OpenWeather => {
+            let (Some(api_key), Some(latitude), Some(longitude)) = (
+                config.weather_api_key.clone(),
+                config.weather_latitude,
+                config.weather_longitude,
+            ) else {
+                warn!("OpenWeather needs weather_api_key, weather_latitude and weather_longitude");
+                return None;
+            };
+            Some(Arc::new(OpenWeather::new(
+                config.weather_url.clone(),
+                api_key,
+                (latitude, longitude),
+                config.weather_units,
+                config.weather_forecast_count,
+                refresh,
+            )))
+        }
+        WeatherSource::Mqtt => {
+            let Some(topic) = config.weather_mqtt_topic.clone() else {
+                warn!("The MQTT weather provider needs weather_mqtt_topic");
+                return None;
+            };
+            if !config.mqtt {
+                warn!("The MQTT weather provider needs mqtt to be enabled");
+            }
+            Some(Arc::new(MqttWeather::new(topic)))
+        }
+    }
+}
+
+/// Fetches the weather on the provider's schedule, or as soon as it has an
+/// update, and sends it to the UI, or `None` once the last good response is
+/// older than `max_age`. Failed fetches keep the cached weather.
+pub async fn run(
+    provider: Arc<dyn WeatherProvider>,
+    max_age: Duration,
+    sender: glib::Sender<Option<Weather>>,
+) {
+    let mut cached: Option<Weather> = None;
+    let mut next_fetch = Instant::now();
+
+    loop {
+        if Instant::now() >= next_fetch {
+            match tokio::time::timeout(FETCH_TIMEOUT, provider.fetch()).await {
+                Ok(Ok(weather)) => {
+                    debug!(?weather, "Fetched weather");
+                    cached = Some(weather);
+                }
+                Ok(Err(e)) => warn!("Failed to fetch weather, {}", e),
+                Err(_) => warn!("Timed out fetching weather"),
+            }
+            next_fetch = Instant::now() + provider.refresh_interval();
+        }
+
+        let fresh = cached.clone().filter(|weather| weather.age() <= max_age);
+        if sender.send(fresh).is_err() {
+            break;
+        }
+
+        tokio::select! {
+            _ = tokio::time::sleep(STALE_CHECK_INTERVAL) => {}
+            _ = provider.updated() => next_fetch = Instant::now(),
+        }
+    }
+}
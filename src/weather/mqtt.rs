@@ -0,0 +1,77 @@
+use std::sync::Mutex;
+use std::time::Duration;
+
+use async_trait::async_trait;
+use gtk::glib;
+use tokio::sync::Notify;
+use tracing::warn;
+
+use super::{Weather, WeatherProvider};
+
+/// Weather pushed over MQTT, e.g. by a Home Assistant automation. The payload
+/// is either a plain temperature or JSON like
+/// `{"temperature": 21.5, "condition": "Cloudy", "forecast": [{"time": "15:00", "temperature": 23}]}`.
+pub struct MqttWeather {
+    topic: String,
+    latest: Mutex<Option<Weather>>,
+    /// Wakes [`super::run`] for every new message
+    received: Notify,
+}
+
+impl MqttWeather {
+    pub fn new(topic: String) -> Self {
+        MqttWeather {
+            topic,
+            latest: Mutex::new(None),
+            received: Notify::new(),
+        }
+    }
+}
+
+#[async_trait]
+impl WeatherProvider for MqttWeather {
+    async fn fetch(&self) -> Result<Weather, String> {
+        self.latest
+            .lock()
+            .unwrap()
+            .clone()
+            .ok_or_else(|| format!("Nothing received on {} yet", self.topic))
+    }
+
+    fn refresh_interval(&self) -> Duration {
+        // Reading the last message is free
+        Duration::ZERO
+    }
+
+    fn mqtt_topic(&self) -> Option<&str> {
+        Some(&self.topic)
+    }
+
+    async fn updated(&self) {
+        self.received.notified().await
+    }
+
+    fn handle_message(&self, payload: &[u8]) {
+        let payload = String::from_utf8_lossy(payload);
+        let parsed = match payload.trim().parse::<f64>() {
+            Ok(temperature) => Ok(Weather {
+                temperature,
+                condition: None,
+                forecast: vec![],
+                observed: 0,
+            }),
+            Err(_) => json5::from_str::<Weather>(&payload),
+        };
+
+        match parsed {
+            Ok(mut weather) => {
+                if weather.observed == 0 {
+                    weather.observed = glib::real_time() / 1_000_000;
+                }
+                self.latest.lock().unwrap().replace(weather);
+                self.received.notify_one();
+            }
+            Err(e) => warn!("Invalid weather payload {}, {}", payload, e),
+        }
+    }
+}
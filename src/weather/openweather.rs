@@ -0,0 +1,147 @@
+use std::time::Duration;
+
+use async_trait::async_trait;
+use gtk::glib::DateTime;
+use serde::Deserialize;
+use tracing::warn;
+
+use super::{Forecast, Weather, WeatherProvider, WeatherUnits};
+
+/// A hung server must not keep stale weather on screen.
+const REQUEST_TIMEOUT: Duration = Duration::from_secs(10);
+
+#[derive(Deserialize)]
+struct CurrentResponse {
+    dt: i64,
+    main: MainResponse,
+    #[serde(default)]
+    weather: Vec<ConditionResponse>,
+}
+
+#[derive(Deserialize)]
+struct ForecastResponse {
+    list: Vec<CurrentResponse>,
+}
+
+#[derive(Deserialize)]
+struct MainResponse {
+    temp: f64,
+}
+
+#[derive(Deserialize)]
+struct ConditionResponse {
+    main: String,
+}
+
+/// The OpenWeather 2.5 API, or anything that speaks it.
+pub struct OpenWeather {
+    client: reqwest::Client,
+    url: String,
+    api_key: String,
+    location: (f64, f64),
+    units: WeatherUnits,
+    forecast_count: usize,
+    refresh: Duration,
+}
+
+impl OpenWeather {
+    pub fn new(
+        url: String,
+        api_key: String,
+        location: (f64, f64),
+        units: WeatherUnits,
+        forecast_count: usize,
+        refresh: Duration,
+    ) -> Self {
+        OpenWeather {
+            client: reqwest::Client::builder()
+                .timeout(REQUEST_TIMEOUT)
+                .build()
+                .unwrap_or_default(),
+            url: url.trim_end_matches('/').to_string(),
+            api_key,
+            location,
+            units,
+            forecast_count,
+            refresh,
+        }
+    }
+
+    async fn get<T: for<'de> Deserialize<'de>>(
+        &self,
+        endpoint: &str,
+        extra: &[(&str, String)],
+    ) -> Result<T, String> {
+        let units = match self.units {
+            WeatherUnits::Metric => "metric",
+            WeatherUnits::Imperial => "imperial",
+        };
+        let mut query = vec![
+            ("lat", self.location.0.to_string()),
+            ("lon", self.location.1.to_string()),
+            ("units", units.to_string()),
+            ("appid", self.api_key.clone()),
+        ];
+        query.extend(extra.iter().cloned());
+
+        self.client
+            .get(format!("{}/{}", self.url, endpoint))
+            .query(&query)
+            .send()
+            .await
+            .and_then(|response| response.error_for_status())
+            .map_err(|e| e.to_string())?
+            .json()
+            .await
+            .map_err(|e| e.to_string())
+    }
+
+    async fn forecast(&self) -> Result<Vec<Forecast>, String> {
+        if self.forecast_count == 0 {
+            return Ok(vec![]);
+        }
+
+        let response: ForecastResponse = self
+            .get("forecast", &[("cnt", self.forecast_count.to_string())])
+            .await?;
+        Ok(response
+            .list
+            .into_iter()
+            .map(|entry| Forecast {
+                time: DateTime::from_unix_local(entry.dt)
+                    .and_then(|time| time.format("%H:%M"))
+                    .map(|time| time.to_string())
+                    .unwrap_or_default(),
+                temperature: entry.main.temp,
+                condition: entry.weather.into_iter().next().map(|c| c.main),
+            })
+            .collect())
+    }
+}
+
+#[async_trait]
+impl WeatherProvider for OpenWeather {
+    async fn fetch(&self) -> Result<Weather, String> {
+        let current: CurrentResponse = self.get("weather", &[]).await?;
+
+        // The current conditions are still worth showing without a forecast
+        let forecast = match self.forecast().await {
+            Ok(forecast) => forecast,
+            Err(e) => {
+                warn!("Failed to fetch the weather forecast, {}", e);
+                vec![]
+            }
+        };
+
+        Ok(Weather {
+            temperature: current.main.temp,
+            condition: current.weather.into_iter().next().map(|c| c.main),
+            forecast,
+            observed: current.dt,
+        })
+    }
+
+    fn refresh_interval(&self) -> Duration {
+        self.refresh
+    }
+}
@@ -1,19 +1,40 @@
+use crate::calendar::{self, AgendaLine, CalendarSettings, Occurrence};
+use crate::display::ambient::{self, AmbientSettings};
+use crate::display::power::{create_backend, DisplayPower};
+use crate::display::presence::{self, PresenceSettings};
+use crate::display::schedule::DisplaySchedule;
+use crate::events::{self, FrameEvent};
+use crate::gui::clock::ClockFormat;
+use crate::gui::night::{NightMode, NightSchedule};
+use crate::gui::overlay::{is_cluttered, OverlayPosition, OverlayWidget};
 use crate::gui::play_pause_button::PpfPlayPauseButton;
-use crate::photo::provider::{Config, FailedFiles, MediaMessage};
+use crate::homeassistant;
+use crate::http::{self, HttpSettings};
+use crate::photo::background::Background;
+use crate::photo::camera::CameraInfoMode;
+use crate::photo::layout::Rect;
+use crate::photo::provider::{
+    config_path, try_load_config, Config, FailedFiles, MediaMessage, PhotoData,
+};
+use crate::photo::saliency::EnergyMap;
 use crate::photo::{Media, MediaProvider};
-use crate::{spawn, spawn_tokio};
+use crate::remote::RemoteCommand;
+use crate::state::{self, update_state};
+use crate::utils::unsafe_wrapper::UnsafeSendSync;
+use crate::weather::{self, Weather};
+use crate::{mqtt, spawn, spawn_tokio, RUNTIME};
+use gtk::gdk_pixbuf::Pixbuf;
 use gtk::glib::{MainContext, PRIORITY_DEFAULT};
-use gtk::MediaFile;
 use gtk::{gio, glib, prelude::*, subclass::prelude::*, CompositeTemplate};
 use gtk::{
-    glib::{clone, timeout_future_seconds},
+    glib::{clone, timeout_future},
     Label,
 };
-use rumqttc::{Event::Incoming, MqttOptions, Packet::Publish, QoS};
-use std::cell::RefCell;
+use gtk::{CssProvider, MediaFile, STYLE_PROVIDER_PRIORITY_APPLICATION};
+use std::cell::{Cell, RefCell};
 use std::sync::{Arc, Mutex};
 use std::time::Duration;
-use tracing::{debug, span, Level};
+use tracing::{debug, span, warn, Level};
 
 mod imp {
     use gtk::Picture;
@@ -23,6 +44,8 @@ mod imp {
     #[derive(Default, Debug, CompositeTemplate)]
     #[template(file = "../data/gtk/window.ui")]
     pub struct PpfWindow {
+        #[template_child]
+        pub(super) overlay: TemplateChild<gtk::Overlay>,
         #[template_child]
         pub(super) play_pause_button: TemplateChild<PpfPlayPauseButton>,
         #[template_child]
@@ -30,18 +53,74 @@ mod imp {
         #[template_child]
         pub(super) date_label: TemplateChild<Label>,
         #[template_child]
+        pub(super) second_time_label: TemplateChild<Label>,
+        #[template_child]
         pub(super) picture: TemplateChild<Picture>,
         #[template_child]
+        pub(super) background_picture: TemplateChild<Picture>,
+        #[template_child]
+        pub(super) pair_box: TemplateChild<gtk::Box>,
+        #[template_child]
+        pub(super) pair_left_picture: TemplateChild<Picture>,
+        #[template_child]
+        pub(super) pair_right_picture: TemplateChild<Picture>,
+        #[template_child]
+        pub(super) collage_fixed: TemplateChild<gtk::Fixed>,
+        #[template_child]
         pub(super) location_label: TemplateChild<Label>,
         #[template_child]
         pub(super) photo_date_label: TemplateChild<Label>,
         #[template_child]
+        pub(super) caption_label: TemplateChild<Label>,
+        #[template_child]
         pub(super) photo_location_label: TemplateChild<Label>,
         #[template_child]
+        pub(super) camera_info_label: TemplateChild<Label>,
+        #[template_child]
         pub(super) location_box: TemplateChild<gtk::Box>,
+        #[template_child]
+        pub(super) time_box: TemplateChild<gtk::Box>,
+        #[template_child]
+        pub(super) play_pause_box: TemplateChild<gtk::Box>,
+        #[template_child]
+        pub(super) map_box: TemplateChild<gtk::Box>,
+        #[template_child]
+        pub(super) minimap_picture: TemplateChild<Picture>,
+        #[template_child]
+        pub(super) weather_box: TemplateChild<gtk::Box>,
+        #[template_child]
+        pub(super) weather_label: TemplateChild<Label>,
+        #[template_child]
+        pub(super) forecast_label: TemplateChild<Label>,
+        #[template_child]
+        pub(super) calendar_box: TemplateChild<gtk::Box>,
+        #[template_child]
+        pub(super) stack: TemplateChild<gtk::Stack>,
+        #[template_child]
+        pub(super) night_box: TemplateChild<gtk::Box>,
+        #[template_child]
+        pub(super) night_time_label: TemplateChild<Label>,
+        #[template_child]
+        pub(super) night_date_label: TemplateChild<Label>,
 
         pub(super) config: RefCell<Config>,
         pub(super) media_provider: RefCell<Arc<Mutex<MediaProvider>>>,
+        pub(super) background_css: CssProvider,
+        pub(super) night_css: CssProvider,
+        pub(super) night_active: Cell<bool>,
+        pub(super) scheduled_off: Cell<bool>,
+        pub(super) too_dark: Cell<bool>,
+        pub(super) nobody_present: Cell<bool>,
+        pub(super) auto_paused: Cell<bool>,
+        /// Whether the slideshow was paused by hand when auto-pause started
+        pub(super) paused_before_auto: Cell<bool>,
+        pub(super) display_powered_off: Cell<bool>,
+        /// Set from a remote command, until the schedule or a sensor changes
+        pub(super) display_override: Cell<Option<bool>>,
+        pub(super) display_power: RefCell<Option<Arc<dyn DisplayPower>>>,
+        pub(super) camera_info_visible: Cell<bool>,
+        /// Set on reload, the timer rebuilds the clock and schedules
+        pub(super) timer_outdated: Cell<bool>,
     }
 
     #[glib::object_subclass]
@@ -103,11 +182,21 @@ impl PpfWindow {
         let imp = imp::PpfWindow::from_obj(&obj);
         imp.config.replace(config.clone());
 
-        let media_provider = Arc::new(Mutex::new(MediaProvider::new(
-            config.clone(),
-            failed_files.clone(),
-        )));
+        let mut provider = MediaProvider::new(config.clone(), failed_files.clone());
+        provider.screen_size = PpfWindow::monitor_size().unwrap_or((1920, 1080));
+        let media_provider = Arc::new(Mutex::new(provider));
         imp.media_provider.replace(media_provider);
+        imp.display_power.replace(Some(create_backend(&config)));
+        update_state(|state| {
+            state.display_on = true;
+            state.interval = config.transition_time;
+        });
+
+        obj.setup_background();
+        obj.setup_overlays();
+        obj.setup_camera_info();
+        obj.setup_night_mode();
+        obj.setup_display_power();
 
         obj.start_worker_thread();
 
@@ -116,7 +205,7 @@ impl PpfWindow {
 
     #[template_callback]
     fn handle_play_pause_toggled(&self, is_paused: bool) {
-        println!("Play/Pause button toggled: {}", is_paused);
+        debug!("Play/Pause button toggled: {}", is_paused);
         match is_paused {
             true => {
                 self.imp().photo_location_label.show();
@@ -126,6 +215,11 @@ impl PpfWindow {
             }
         }
 
+        if self.imp().config.borrow().camera_info == CameraInfoMode::Paused {
+            self.imp().camera_info_visible.set(is_paused);
+            self.update_camera_info();
+        }
+
         self.imp()
             .media_provider
             .clone()
@@ -133,22 +227,403 @@ impl PpfWindow {
             .lock()
             .unwrap()
             .paused = is_paused;
+        update_state(|state| state.paused = is_paused);
+        events::emit(if is_paused {
+            FrameEvent::Paused
+        } else {
+            FrameEvent::Resumed
+        });
+    }
+
+    fn setup_camera_info(&self) {
+        let mode = self.imp().config.borrow().camera_info;
+        self.imp()
+            .camera_info_visible
+            .set(mode == CameraInfoMode::Always);
+
+        // Buttons claim their clicks, so this only sees taps on the photo
+        let gesture = gtk::GestureClick::new();
+        gesture.connect_released(clone!(@weak self as this => move |_, n_press, _, _| {
+            if n_press == 1 {
+                this.toggle_camera_info();
+            }
+        }));
+        self.add_controller(gesture);
+    }
+
+    fn toggle_camera_info(&self) {
+        if self.imp().config.borrow().camera_info == CameraInfoMode::Off {
+            return;
+        }
+
+        let visible = !self.imp().camera_info_visible.get();
+        debug!(visible, "Toggling camera info");
+        self.imp().camera_info_visible.set(visible);
+        self.update_camera_info();
+    }
+
+    fn update_camera_info(&self) {
+        let label = &self.imp().camera_info_label;
+        label.set_visible(self.imp().camera_info_visible.get() && !label.text().is_empty());
+    }
+
+    fn setup_night_mode(&self) {
+        let imp = self.imp();
+        if let Some(display) = gtk::gdk::Display::default() {
+            gtk::style_context_add_provider_for_display(
+                &display,
+                &imp.night_css,
+                STYLE_PROVIDER_PRIORITY_APPLICATION + 1,
+            );
+        }
+        self.load_night_css();
+    }
+
+    fn load_night_css(&self) {
+        let imp = self.imp();
+        let config = imp.config.borrow();
+        imp.night_css.load_from_data(&format!(
+            ".night {{ filter: sepia({}) brightness({}); }}",
+            config.night_warmth.clamp(0.0, 1.0),
+            config.night_brightness.clamp(0.0, 1.0)
+        ));
+    }
+
+    /// Switches to the night clock, or dims the slideshow, depending on
+    /// `night_mode`.
+    fn set_night_mode(&self, active: bool) {
+        let imp = self.imp();
+        debug!(active, "Switching night mode");
+        imp.night_active.set(active);
+
+        let mode = imp.config.borrow().night_mode;
+        match mode {
+            NightMode::Clock => {
+                if active {
+                    imp.stack.set_visible_child(&*imp.night_box);
+                } else {
+                    imp.stack.set_visible_child(&*imp.overlay);
+                }
+                imp.media_provider.borrow().lock().unwrap().clock_only = active;
+            }
+            NightMode::Dim => {
+                if active {
+                    imp.overlay.add_css_class("night");
+                } else {
+                    imp.overlay.remove_css_class("night");
+                }
+            }
+        }
+    }
+
+    fn display_power(&self) -> Option<Arc<dyn DisplayPower>> {
+        self.imp().display_power.borrow().clone()
+    }
+
+    fn setup_display_power(&self) {
+        let Some(brightness) = self.imp().config.borrow().display_brightness else {
+            return;
+        };
+        if let Some(power) = self.display_power() {
+            RUNTIME.spawn_blocking(move || {
+                if let Err(e) = power.set_brightness(brightness) {
+                    warn!("Failed to set display brightness, {}", e);
+                    events::emit_error(format!("Failed to set display brightness, {}", e));
+                }
+            });
+        }
+    }
+
+    /// Powers the display off and pauses the slideshow, or the reverse.
+    fn set_scheduled_off(&self, off: bool) {
+        debug!(off, "Switching display by schedule");
+        self.imp().scheduled_off.set(off);
+        self.imp().display_override.set(None);
+        self.update_display_power();
+        self.update_auto_pause();
+    }
+
+    fn set_present(&self, present: bool) {
+        debug!(present, "Presence changed");
+        self.imp().nobody_present.set(!present);
+        self.imp().display_override.set(None);
+        self.update_display_power();
+        self.update_auto_pause();
+    }
+
+    /// Pauses the slideshow through the play/pause button while the schedule,
+    /// a dark or an empty room turn the display off. Only resumes it when it
+    /// was playing before.
+    fn update_auto_pause(&self) {
+        let imp = self.imp();
+        let paused = imp.scheduled_off.get() || imp.too_dark.get() || imp.nobody_present.get();
+        if paused == imp.auto_paused.get() {
+            return;
+        }
+
+        imp.auto_paused.set(paused);
+        if paused {
+            let was_paused = imp.play_pause_button.property::<bool>("is-paused");
+            imp.paused_before_auto.set(was_paused);
+            imp.play_pause_button.set_property("is-paused", true);
+        } else if !imp.paused_before_auto.get() {
+            imp.play_pause_button.set_property("is-paused", false);
+        }
+    }
+
+    fn set_too_dark(&self, dark: bool) {
+        debug!(dark, "Room light changed");
+        self.imp().too_dark.set(dark);
+        self.imp().display_override.set(None);
+        self.update_display_power();
+        self.update_auto_pause();
+    }
+
+    /// The display is on unless the schedule, the room's darkness or nobody
+    /// being around turn it off, or a remote command overrides them.
+    fn update_display_power(&self) {
+        let imp = self.imp();
+        let off = match imp.display_override.get() {
+            Some(on) => !on,
+            None => imp.scheduled_off.get() || imp.too_dark.get() || imp.nobody_present.get(),
+        };
+        if off == imp.display_powered_off.get() {
+            return;
+        }
+
+        imp.display_powered_off.set(off);
+        update_state(|state| state.display_on = !off);
+        events::emit(FrameEvent::Display { on: !off });
+        if let Some(power) = self.display_power() {
+            RUNTIME.spawn_blocking(move || {
+                if let Err(e) = power.set_power(!off) {
+                    warn!("Failed to switch display, {}", e);
+                    events::emit_error(format!("Failed to switch display, {}", e));
+                }
+            });
+        }
+    }
+
+    fn monitor_size() -> Option<(i32, i32)> {
+        let display = gtk::gdk::Display::default()?;
+        let monitor = display
+            .monitors()
+            .item(0)?
+            .downcast::<gtk::gdk::Monitor>()
+            .ok()?;
+        let geometry = monitor.geometry();
+
+        Some((geometry.width(), geometry.height()))
+    }
+
+    fn setup_background(&self) {
+        if let Some(display) = gtk::gdk::Display::default() {
+            gtk::style_context_add_provider_for_display(
+                &display,
+                &self.imp().background_css,
+                STYLE_PROVIDER_PRIORITY_APPLICATION + 1,
+            );
+        }
+
+        self.set_background(&Background::None);
+    }
+
+    fn set_background(&self, background: &Background) {
+        let imp = self.imp();
+        let color = match background {
+            Background::Color(r, g, b) => Some(format!("rgb({}, {}, {})", r, g, b)),
+            _ => imp.config.borrow().background_color.clone(),
+        };
+
+        match color {
+            Some(color) if gtk::gdk::RGBA::parse(color.as_str()).is_ok() => {
+                imp.background_css
+                    .load_from_data(&format!(".background {{ background-color: {}; }}", color));
+            }
+            Some(color) => {
+                warn!("Invalid background color {}", color);
+                imp.background_css.load_from_data("");
+            }
+            None => imp.background_css.load_from_data(""),
+        }
+
+        if let Background::Image(pixbuf) = background {
+            imp.background_picture.set_pixbuf(Some(pixbuf.as_ref()));
+            imp.background_picture.show();
+        } else {
+            imp.background_picture.hide();
+            imp.background_picture.set_pixbuf(None);
+        }
+    }
+
+    fn overlay_container(&self, widget: OverlayWidget) -> gtk::Widget {
+        let imp = self.imp();
+        match widget {
+            OverlayWidget::Clock => imp.time_box.get().upcast(),
+            OverlayWidget::Location => imp.location_box.get().upcast(),
+            OverlayWidget::Pause => imp.play_pause_box.get().upcast(),
+            OverlayWidget::Map => imp.map_box.get().upcast(),
+            OverlayWidget::Weather => imp.weather_box.get().upcast(),
+            OverlayWidget::Calendar => imp.calendar_box.get().upcast(),
+        }
+    }
+
+    fn is_overlay_enabled(&self, widget: OverlayWidget) -> bool {
+        self.imp()
+            .config
+            .borrow()
+            .overlays
+            .iter()
+            .any(|overlay| overlay.widget == widget)
+    }
+
+    fn setup_overlays(&self) {
+        let imp = self.imp();
+        let overlays = imp.config.borrow().overlays.clone();
+        for widget in OverlayWidget::ALL {
+            if !overlays.iter().any(|overlay| overlay.widget == widget) {
+                self.overlay_container(widget).hide();
+            }
+        }
+
+        // Widgets sharing a corner are stacked in the order they are listed
+        let mut corners: Vec<(OverlayPosition, gtk::Box)> = vec![];
+        for overlay in &overlays {
+            let container = self.overlay_container(overlay.widget);
+            let (halign, valign) = overlay.position.align();
+            let corner = match corners.iter().find(|(p, _)| *p == overlay.position) {
+                Some((_, corner)) => corner.clone(),
+                None => {
+                    let corner = gtk::Box::new(gtk::Orientation::Vertical, 0);
+                    corner.set_halign(halign);
+                    corner.set_valign(valign);
+                    imp.overlay.add_overlay(&corner);
+                    corners.push((overlay.position, corner.clone()));
+                    corner
+                }
+            };
+            let overlay_widget = imp.overlay.get().upcast::<gtk::Widget>();
+            if container
+                .parent()
+                .is_some_and(|parent| parent == overlay_widget)
+            {
+                imp.overlay.remove_overlay(&container);
+                corner.append(&container);
+            }
+
+            container.set_halign(halign);
+            container.set_valign(valign);
+            container.set_opacity(overlay.opacity);
+            if let Some(css_class) = overlay.size.css_class() {
+                container.add_css_class(css_class);
+            }
+
+            // Line the contents up with the closest screen edge
+            let mut child = container.first_child();
+            while let Some(c) = child {
+                c.set_halign(halign);
+                child = c.next_sibling();
+            }
+        }
+    }
+
+    /// Fades out the overlays that would cover a bright or busy part of the
+    /// photo. `photo` is the energy map with the size of the shown photo, or
+    /// `None` to show all overlays.
+    fn update_overlay_auto_hide(&self, photo: Option<(&EnergyMap, i32, i32)>) {
+        let overlays = self.imp().config.borrow().overlays.clone();
+        for overlay in overlays {
+            let container = self.overlay_container(overlay.widget);
+            let hidden = overlay.auto_hide
+                && photo.is_some_and(|(map, width, height)| {
+                    self.covers_cluttered_area(&container, map, width, height)
+                });
+
+            container.set_opacity(if hidden { 0.0 } else { overlay.opacity });
+            container.set_can_target(!hidden);
+        }
+    }
+
+    fn covers_cluttered_area(
+        &self,
+        widget: &gtk::Widget,
+        map: &EnergyMap,
+        photo_width: i32,
+        photo_height: i32,
+    ) -> bool {
+        let Some(bounds) = widget.compute_bounds(self) else {
+            return false;
+        };
+        let (width, height) = (self.width() as f64, self.height() as f64);
+        if photo_width <= 0 || photo_height <= 0 || width <= 0.0 || height <= 0.0 {
+            return false;
+        }
+
+        // The photo is scaled to fit and centered in the window
+        let scale = (width / photo_width as f64).min(height / photo_height as f64);
+        let (shown_width, shown_height) = (photo_width as f64 * scale, photo_height as f64 * scale);
+        let (left, top) = ((width - shown_width) / 2.0, (height - shown_height) / 2.0);
+
+        let x0 = (bounds.x() as f64).max(left);
+        let y0 = (bounds.y() as f64).max(top);
+        let x1 = ((bounds.x() + bounds.width()) as f64).min(left + shown_width);
+        let y1 = ((bounds.y() + bounds.height()) as f64).min(top + shown_height);
+        if x1 <= x0 || y1 <= y0 {
+            // Only covers the background
+            return false;
+        }
+
+        is_cluttered(
+            map,
+            (x0 - left) / shown_width,
+            (y0 - top) / shown_height,
+            (x1 - x0) / shown_width,
+            (y1 - y0) / shown_height,
+        )
     }
 
     pub async fn start_timer(&self) {
-        loop {
-            timeout_future_seconds(1).await;
+        let mut clock = ClockFormat::new(&self.imp().config.borrow());
+        let mut night_schedule = NightSchedule::new(&self.imp().config.borrow());
+        let mut display_schedule = DisplaySchedule::new(&self.imp().config.borrow());
 
+        loop {
+            if self.imp().timer_outdated.replace(false) {
+                let config = self.imp().config.borrow();
+                clock = ClockFormat::new(&config);
+                night_schedule = NightSchedule::new(&config);
+                display_schedule = DisplaySchedule::new(&config);
+            }
             let now = gtk::glib::DateTime::now_local().unwrap();
-            let time_str = now.format("%H:%M").unwrap();
-            let date_str = now.format("%A, %d %B %Y").unwrap();
 
+            let night = night_schedule.is_some_and(|schedule| schedule.is_night(&now));
+            if night != self.imp().night_active.get() {
+                self.set_night_mode(night);
+            }
+            let off = display_schedule
+                .as_ref()
+                .is_some_and(|schedule| !schedule.is_on(&now));
+            if off != self.imp().scheduled_off.get() {
+                self.set_scheduled_off(off);
+            }
+
+            self.imp().time_label.set_text(clock.time(&now).as_str());
+            self.imp().date_label.set_text(clock.date(&now).as_str());
             self.imp()
-                .time_label
-                .set_text(time_str.to_string().as_str());
+                .night_time_label
+                .set_text(clock.time(&now).as_str());
             self.imp()
-                .date_label
-                .set_text(date_str.to_string().as_str());
+                .night_date_label
+                .set_text(clock.date(&now).as_str());
+            match clock.second_clock(&now) {
+                Some(second_time) => {
+                    self.imp().second_time_label.set_text(second_time.as_str());
+                    self.imp().second_time_label.show();
+                }
+                None => self.imp().second_time_label.hide(),
+            }
+
+            timeout_future(clock.next_tick(&now)).await;
         }
     }
 
@@ -160,6 +635,7 @@ impl PpfWindow {
 
         let this = self;
 
+        #[rustfmt::skip]
         media_receiver.attach(None, clone!(@weak this => @default-return Continue(false), move |photo_obj| {
           match photo_obj {
             MediaMessage::Photo { photo, photo_data, address } => {
@@ -169,57 +645,83 @@ impl PpfWindow {
                 debug!("Recreating photo");
 
                 this.imp().picture.set_pixbuf(Some(photo_data.pixbuf.as_ref()));
+                this.show_only(&*this.imp().picture);
+                this.set_background(&photo_data.background);
+                this.update_overlay_auto_hide(
+                    photo_data.energy.as_ref().map(|map| {
+                        (map, photo_data.pixbuf.width(), photo_data.pixbuf.height())
+                    })
+                );
 
                 debug!("Done setting photo on screen");
 
-                let mut location_found = false;
-                let mut date_found = false;
-
-                match address {
-                    Ok(a) => {
-                        location_found = true;
-                        this.imp().location_label.set_text(format!("{}", a).as_str());
-                    },
-                    Err(e) => {
-                        this.imp().location_label.set_text("");
-                        println!("Failed to get reverse geocode response, {}", e);
-                    }
-                }
+                this.show_minimap(photo_data.minimap.as_ref());
+                this.show_photo_details(&[&photo], address);
+                debug!("Done setting everything on screen");
+            },
+            MediaMessage::PhotoPair { photos, photo_data, address } => {
+                let span = span!(Level::TRACE, "show_picture_pair_thread");
+                let _enter = span.enter();
 
-                if let Media::Photo { path, orientation: _, location: _, date } = photo {
-                    if let Some(string_date) = date {
-                        date_found = true;
-                        this.imp().photo_date_label.set_text(string_date.as_str());
-                    } else {
-                        this.imp().photo_date_label.set_text("");
-                    }
+                this.imp().pair_left_picture.set_pixbuf(Some(photo_data.0.pixbuf.as_ref()));
+                this.imp().pair_right_picture.set_pixbuf(Some(photo_data.1.pixbuf.as_ref()));
+                this.show_only(&*this.imp().pair_box);
+                this.set_background(&photo_data.0.background);
+                this.update_overlay_auto_hide(None);
 
-                    this.imp().photo_location_label.set_text(format!("{}", path.to_str().unwrap()).as_str());
-                }
+                debug!("Done setting photo pair on screen");
 
-                if location_found || date_found {
-                    this.imp().location_box.show();
-                } else {
-                    this.imp().location_box.hide();
+                this.show_minimap(photo_data.0.minimap.as_ref());
+                this.show_photo_details(&[&photos.0, &photos.1], address);
+            },
+            MediaMessage::Collage { photos, photo_data, layout, address } => {
+                let span = span!(Level::TRACE, "show_collage_thread");
+                let _enter = span.enter();
+
+                this.show_collage(&photo_data, &layout);
+                this.show_only(&*this.imp().collage_fixed);
+                if let Some(first) = photo_data.first() {
+                    this.set_background(&first.background);
                 }
-                debug!("Done setting everything on screen");
+                this.update_overlay_auto_hide(None);
+
+                debug!("Done setting collage on screen");
+
+                this.show_minimap(photo_data.iter().find_map(|data| data.minimap.as_ref()));
+                let photos: Vec<&Media> = photos.iter().collect();
+                this.show_photo_details(&photos, address);
             },
             MediaMessage::Video { video: video_file } => {
               if let Media::Video { path } = video_file {
-                  println!("Got a video, trying to play it {}", path.to_str().unwrap());
+                  debug!("Got a video, trying to play it {:?}", path);
+                  update_state(|state| {
+                      state.photo = path.to_str().map(|path| path.to_string());
+                      state.date = None;
+                      state.location = None;
+                      state.caption = None;
+                      state.camera = None;
+                  });
+                  events::emit(FrameEvent::SlideChanged {
+                      photo: path.to_str().map(|path| path.to_string()),
+                      date: None,
+                      location: None,
+                      caption: None,
+                      camera: None,
+                  });
                   let media_file = MediaFile::new();
                   let file = gtk::gio::File::for_path(path);
                   media_file.set_file(Some(&file));
 
                   media_file.connect_playing_notify(
                       move |media_file| {
-                          println!("Media is playing: {}", media_file.is_playing());
+                          debug!("Media is playing: {}", media_file.is_playing());
                       }
                   );
                   media_file.connect_error_notify(
                       move |media_file| {
                           let error = media_file.error().unwrap();
-                          println!("Error in MediaFile: {}", error);
+                          warn!("Error in MediaFile: {}", error);
+                          events::emit_error(format!("Failed to play video, {}", error));
                       }
                   );
                   media_file.connect_prepared_notify(
@@ -228,12 +730,16 @@ impl PpfWindow {
                               return;
                           }
                           if !media_file.has_video() {
-                              println!("Media is not a valid video file");
+                              warn!("Media is not a valid video file");
                               return;
                           }
                       }
                   );
 
+                  this.set_background(&Background::None);
+                  this.update_overlay_auto_hide(None);
+                  this.show_minimap(None);
+                  this.show_only(&*this.imp().picture);
                   this.imp().picture.set_paintable(Some(&media_file));
                   media_file.play();
               }
@@ -243,161 +749,456 @@ impl PpfWindow {
       }));
 
         let config = self.imp().config.borrow();
-        let mqtt_host = config.mqtt_host.clone();
-        let mqtt_user = config.mqtt_user.clone();
-        let mqtt_password = config.mqtt_password.clone();
-        let mqtt_topic = config.mqtt_topic.clone();
-        let mqtt_topic_clone = mqtt_topic.clone();
+        let weather_provider = weather::create_provider(&config);
+        if let Some(provider) = weather_provider.clone() {
+            let (sender, receiver) = MainContext::channel::<Option<Weather>>(PRIORITY_DEFAULT);
+            let max_age = Duration::from_secs(config.weather_max_age_minutes as u64 * 60);
+            spawn_tokio!(weather::run(provider, max_age, sender));
 
-        let mqtt_host_clone = mqtt_host.clone();
-        let mqtt_user_clone = mqtt_user.clone();
-        let mqtt_password_clone = mqtt_password.clone();
-        let mqtt_topic_clone_2 = mqtt_topic.clone();
+            receiver.attach(
+                None,
+                clone!(@weak this => @default-return Continue(false), move |weather| {
+                  this.show_weather(weather.as_ref());
+                  Continue(true)
+                }),
+            );
+        }
 
-        if config.mqtt {
+        if let Some(settings) = CalendarSettings::new(&config) {
+            let (sender, receiver) = MainContext::channel::<Vec<Occurrence>>(PRIORITY_DEFAULT);
+            spawn_tokio!(calendar::run(settings, sender));
+
+            receiver.attach(
+                None,
+                clone!(@weak this => @default-return Continue(false), move |occurrences| {
+                  this.show_calendar(&occurrences);
+                  Continue(true)
+                }),
+            );
+        }
+
+        let (lux_sender, lux_receiver) = tokio::sync::mpsc::unbounded_channel();
+        let ambient = AmbientSettings::new(&config);
+        let ambient_topic = ambient
+            .as_ref()
+            .and_then(|settings| settings.mqtt_topic())
+            .map(|topic| topic.to_string());
+        if let (Some(settings), Some(power)) = (ambient, self.display_power()) {
             let (sender, receiver) = MainContext::channel::<bool>(PRIORITY_DEFAULT);
-            let (mut client, mut eventloop) =
-                PpfWindow::connect_mqtt_async(mqtt_host.clone(), mqtt_user, mqtt_password);
+            spawn_tokio!(ambient::run(settings, power, lux_receiver, sender));
 
-            let mut mqtt_client_clone = client.clone();
+            receiver.attach(
+                None,
+                clone!(@weak this => @default-return Continue(false), move |dark| {
+                  this.set_too_dark(dark);
+                  Continue(true)
+                }),
+            );
+        }
 
-            spawn_tokio!(async move {
-                let mut retry_count = 0;
-                let max_retries = 100;
-                let base_delay = Duration::from_secs(1);
-                let max_delay = Duration::from_secs(60);
+        let (occupancy_sender, occupancy_receiver) = tokio::sync::mpsc::unbounded_channel();
+        let presence = PresenceSettings::new(&config);
+        let presence_topic = presence
+            .as_ref()
+            .and_then(|settings| settings.mqtt_topic())
+            .map(|topic| topic.to_string());
+        if let Some(settings) = presence {
+            let (sender, receiver) = MainContext::channel::<bool>(PRIORITY_DEFAULT);
+            spawn_tokio!(presence::run(settings, occupancy_receiver, sender));
+
+            receiver.attach(
+                None,
+                clone!(@weak this => @default-return Continue(false), move |present| {
+                  this.set_present(present);
+                  Continue(true)
+                }),
+            );
+        }
 
-                if let Err(e) = PpfWindow::subscribe_mqtt_async(&mut client, &mqtt_topic).await {
-                    println!("Failed to subscribe to topics: {}", e);
+        let (sender, receiver) = MainContext::channel::<RemoteCommand>(PRIORITY_DEFAULT);
+        receiver.attach(
+            None,
+            clone!(@weak this => @default-return Continue(false), move |command| {
+              this.handle_command(command);
+              Continue(true)
+            }),
+        );
+
+        if let Some(settings) = HttpSettings::new(&config) {
+            let (command_sender, mut command_receiver) = tokio::sync::mpsc::unbounded_channel();
+            let sender = sender.clone();
+            spawn_tokio!(async move {
+                while let Some(command) = command_receiver.recv().await {
+                    if sender.send(command).is_err() {
+                        break;
+                    }
                 }
+            });
+            spawn_tokio!(http::run(settings, command_sender));
+        }
 
-                'connection: loop {
-                    match eventloop.poll().await {
-                        Ok(notification) => {
-                            println!("Event = {notification:?}");
-                            match notification {
-                                Incoming(Publish(notification)) => {
-                                    if notification.topic == mqtt_topic_clone {
-                                        let payload =
-                                            String::from_utf8(notification.payload[..].to_vec())
-                                                .unwrap();
-                                        let power = if payload == "1" { "0" } else { "1" };
-                                        println!("Received MQTT notification {}", payload);
-                                        let err = run_script::run_script!(format!(
-                                            "echo {} | sudo tee /sys/class/backlight/*/bl_power",
-                                            power
-                                        ));
-                                        if err.is_err() {
-                                            println!("Failed to switch lcd display");
-                                        }
-
-                                        if payload == "1" {
-                                            sender.send(false).unwrap();
-                                        } else {
-                                            sender.send(true).unwrap();
-                                        }
-                                    }
-                                }
-                                _ => {}
-                            }
-
-                            retry_count = 0;
-                        }
+        if config.mqtt {
+            let mqtt_topic = config.mqtt_topic.clone();
+            let weather_topic = weather_provider
+                .as_ref()
+                .and_then(|provider| provider.mqtt_topic())
+                .map(|topic| topic.to_string());
+            let command_prefix = format!("{}/cmd/", config.mqtt_base_topic);
+            let topics = [
+                Some(mqtt_topic.clone()),
+                Some(format!("{}+", command_prefix)),
+                weather_topic.clone(),
+                ambient_topic.clone(),
+                presence_topic.clone(),
+            ]
+            .into_iter()
+            .flatten()
+            .collect();
 
-                        Err(e) => {
-                            println!("Error in eventloop: {}", e);
-
-                            // Disconnect the client
-                            if let Err(e) = mqtt_client_clone.disconnect().await {
-                                println!("Error disconnecting: {}", e);
-                            }
-
-                            retry_count += 1;
-
-                            if retry_count > max_retries {
-                                println!("Maximum retry attempts reached. Exiting.");
-                                break 'connection;
-                            }
-
-                            // Calculate exponential backoff with jitter
-                            let delay = std::cmp::min(
-                                base_delay * 2u32.pow(retry_count as u32 - 1),
-                                max_delay,
-                            );
-
-                            // Add jitter: randomly adjust delay by ±25%
-                            let jitter =
-                                delay.as_millis() as f64 * (rand::random::<f64>() * 0.5 - 0.25);
-                            let delay_with_jitter =
-                                Duration::from_millis((delay.as_millis() as f64 + jitter) as u64);
-
-                            println!(
-                                "Retry attempt {} of {}. Waiting for {:?} before reconnecting",
-                                retry_count, max_retries, delay_with_jitter
-                            );
-
-                            tokio::time::sleep(delay_with_jitter).await;
-
-                            // Attempt to reconnect
-                            let (new_client, new_eventloop) = PpfWindow::connect_mqtt_async(
-                                mqtt_host_clone.clone(),
-                                mqtt_user_clone.clone(),
-                                mqtt_password_clone.clone(),
-                            );
-                            client = new_client;
-                            eventloop = new_eventloop;
-                            mqtt_client_clone = client.clone();
-
-                            // Resubscribe
-                            if let Err(e) =
-                                PpfWindow::subscribe_mqtt_async(&mut client, &mqtt_topic_clone_2)
-                                    .await
-                            {
-                                println!("Error resubscribing to topics: {}", e);
-                                continue;
-                            }
-
-                            println!("Successfully reconnected");
+            let publisher = mqtt::start(&config, topics, move |topic, payload| {
+                if weather_topic.as_deref() == Some(topic) {
+                    if let Some(provider) = &weather_provider {
+                        provider.handle_message(payload);
+                    }
+                }
+                if ambient_topic.as_deref() == Some(topic) {
+                    if let Some(lux) = ambient::parse_lux(payload) {
+                        let _ = lux_sender.send(lux);
+                    }
+                }
+                if presence_topic.as_deref() == Some(topic) {
+                    if let Some(occupancy) = presence::parse_occupancy(payload) {
+                        let _ = occupancy_sender.send(occupancy);
+                    }
+                }
+                if let Some(name) = topic.strip_prefix(&command_prefix) {
+                    match RemoteCommand::parse(name, payload) {
+                        Ok(command) => {
+                            // Only fails while the window is closing
+                            let _ = sender.send(command);
                         }
+                        Err(e) => warn!("Ignoring MQTT command on {}, {}", topic, e),
                     }
+                    return;
+                }
+                if topic != mqtt_topic {
+                    return;
+                }
+
+                let payload = String::from_utf8_lossy(payload);
+                debug!(%payload, "Received MQTT notification");
+                if payload == "info" {
+                    let _ = sender.send(RemoteCommand::ToggleCameraInfo);
+                } else {
+                    // Through the window, so the state and schedules know about it
+                    let _ = sender.send(RemoteCommand::SetDisplay(payload == "1"));
+                    let _ = sender.send(RemoteCommand::SetPaused(payload != "1"));
                 }
             });
 
-            receiver.attach(
-                None,
-                clone!(@weak this => @default-return Continue(false), move |is_paused| {
-                  this.imp().play_pause_button.set_property("is-paused", is_paused);
+            if config.mqtt_discovery {
+                homeassistant::publish_discovery(&config, &publisher);
+            }
+            spawn_tokio!(state::publish_to_mqtt(
+                publisher,
+                config.mqtt_base_topic.clone()
+            ));
+        }
+    }
 
-                  if !is_paused {
-                    this.imp().photo_location_label.hide();
-                  }
-                  Continue(true)
-                }),
-            );
+    /// Applies the photo, background, camera info, clock, schedule and night
+    /// settings from the config file. Overlays and connections only change
+    /// after a restart.
+    fn reload_config(&self) -> Result<(), String> {
+        let config = try_load_config()?;
+        let imp = self.imp();
+        // Undone with the old night_mode, the timer picks the new schedule up
+        let night = imp.night_active.get();
+        if night {
+            self.set_night_mode(false);
         }
+        imp.config.replace(config.clone());
+        imp.timer_outdated.set(true);
+        imp.media_provider
+            .borrow()
+            .lock()
+            .unwrap()
+            .set_config(config.clone());
+
+        imp.camera_info_visible
+            .set(config.camera_info == CameraInfoMode::Always);
+        self.update_camera_info();
+        self.load_night_css();
+        if night {
+            self.set_night_mode(true);
+        }
+        debug!("Reloaded config from {:?}", config_path());
+        events::emit(FrameEvent::ConfigReloaded);
+        Ok(())
     }
 
-    fn connect_mqtt_async(
-        mqtt_host: String,
-        mqtt_user: Option<String>,
-        mqtt_password: Option<String>,
-    ) -> (rumqttc::AsyncClient, rumqttc::EventLoop) {
-        let mut mqtt_options = MqttOptions::new("pi-photo-frame", mqtt_host, 1883);
-        mqtt_options.set_keep_alive(Duration::from_secs(5));
-        mqtt_options.set_clean_session(false);
-        if let (Some(username), Some(password)) = (mqtt_user, mqtt_password) {
-            mqtt_options.set_credentials(username, password);
+    pub fn handle_command(&self, command: RemoteCommand) {
+        debug!(?command, "Handling remote command");
+        let media_provider = self.imp().media_provider.borrow().clone();
+        let result = match command {
+            RemoteCommand::SetPaused(is_paused) => {
+                self.imp()
+                    .play_pause_button
+                    .set_property("is-paused", is_paused);
+
+                if !is_paused {
+                    self.imp().photo_location_label.hide();
+                }
+                Ok(())
+            }
+            RemoteCommand::SetDisplay(on) => {
+                self.imp().display_override.set(Some(on));
+                self.update_display_power();
+                Ok(())
+            }
+            RemoteCommand::ToggleCameraInfo => {
+                self.toggle_camera_info();
+                Ok(())
+            }
+            RemoteCommand::Next => {
+                media_provider.lock().unwrap().next();
+                Ok(())
+            }
+            RemoteCommand::Previous => {
+                media_provider.lock().unwrap().previous();
+                Ok(())
+            }
+            RemoteCommand::Show(path) => media_provider.lock().unwrap().show(path),
+            RemoteCommand::Album(album) => {
+                media_provider.lock().unwrap().set_album(album.as_deref())
+            }
+            RemoteCommand::Interval(seconds) => {
+                media_provider.lock().unwrap().set_interval(seconds);
+                Ok(())
+            }
+            RemoteCommand::Favorite(path) => media_provider.lock().unwrap().favorite(path),
+            RemoteCommand::Unfavorite(path) => media_provider.lock().unwrap().unfavorite(path),
+            RemoteCommand::Hide(path) => media_provider.lock().unwrap().hide(path),
+            RemoteCommand::Unhide(path) => media_provider.lock().unwrap().unhide(path),
+            RemoteCommand::ReloadConfig => self.reload_config(),
+            RemoteCommand::Brightness(level) => {
+                if let Some(power) = self.display_power() {
+                    RUNTIME.spawn_blocking(move || {
+                        if let Err(e) = power.set_brightness(level) {
+                            warn!("Failed to set display brightness, {}", e);
+                            events::emit_error(format!("Failed to set display brightness, {}", e));
+                        }
+                    });
+                }
+                Ok(())
+            }
+        };
+
+        if let Err(e) = result {
+            warn!("Remote command failed, {}", e);
+            events::emit_error(format!("Remote command failed, {}", e));
+        }
+
+        let media_provider = media_provider.lock().unwrap();
+        update_state(|state| {
+            state.album = media_provider.album_name();
+            state.interval = media_provider.interval;
+        });
+    }
+
+    /// Shows one of the photo containers and hides the others.
+    fn show_only(&self, container: &impl IsA<gtk::Widget>) {
+        let imp = self.imp();
+        let containers: [&gtk::Widget; 3] = [
+            imp.picture.upcast_ref(),
+            imp.pair_box.upcast_ref(),
+            imp.collage_fixed.upcast_ref(),
+        ];
+
+        for widget in containers {
+            widget.set_visible(widget == container.upcast_ref::<gtk::Widget>());
         }
+    }
 
-        let (client, eventloop) = rumqttc::AsyncClient::new(mqtt_options, 10);
+    fn show_collage(&self, photo_data: &[PhotoData], layout: &[Rect]) {
+        let collage = &self.imp().collage_fixed;
+        while let Some(child) = collage.first_child() {
+            collage.remove(&child);
+        }
 
-        (client, eventloop)
+        for (data, rect) in photo_data.iter().zip(layout.iter()) {
+            let picture = gtk::Picture::for_pixbuf(data.pixbuf.as_ref());
+            picture.set_size_request(rect.width, rect.height);
+            collage.put(&picture, rect.x as f64, rect.y as f64);
+        }
     }
 
-    async fn subscribe_mqtt_async(
-        client: &mut rumqttc::AsyncClient,
-        mqtt_topic: &String,
-    ) -> Result<(), rumqttc::ClientError> {
-        client.subscribe(mqtt_topic, QoS::AtMostOnce).await
+    fn show_minimap(&self, minimap: Option<&Arc<UnsafeSendSync<Pixbuf>>>) {
+        let imp = self.imp();
+        match minimap {
+            Some(pixbuf) if self.is_overlay_enabled(OverlayWidget::Map) => {
+                imp.minimap_picture.set_pixbuf(Some(pixbuf.as_ref()));
+                imp.map_box.show();
+            }
+            _ => {
+                imp.map_box.hide();
+                imp.minimap_picture.set_pixbuf(None);
+            }
+        }
+    }
+
+    fn show_weather(&self, weather: Option<&Weather>) {
+        let imp = self.imp();
+        let Some(weather) = weather else {
+            imp.weather_box.hide();
+            return;
+        };
+
+        imp.weather_label.set_text(weather.current().as_str());
+        let forecast = weather.forecast(imp.config.borrow().weather_forecast_count);
+        imp.forecast_label.set_text(forecast.as_str());
+        imp.forecast_label.set_visible(!forecast.is_empty());
+        imp.weather_box.show();
+    }
+
+    fn show_calendar(&self, occurrences: &[Occurrence]) {
+        let imp = self.imp();
+        while let Some(child) = imp.calendar_box.first_child() {
+            imp.calendar_box.remove(&child);
+        }
+
+        let time_format = if imp.config.borrow().clock_24h {
+            "%H:%M"
+        } else {
+            "%-I:%M %p"
+        };
+        // Lined up like the box, with the corner it is configured in
+        let halign = imp.calendar_box.halign();
+        for line in calendar::agenda_lines(occurrences, time_format) {
+            let (text, css_class) = match line {
+                AgendaLine::Day(text) => (text, "calendar-day"),
+                AgendaLine::Event(text) => (text, "calendar-event"),
+            };
+            let label = Label::new(Some(&text));
+            label.set_halign(halign);
+            label.add_css_class(css_class);
+            imp.calendar_box.append(&label);
+        }
+
+        imp.calendar_box
+            .set_visible(imp.calendar_box.first_child().is_some());
+    }
+
+    /// Fills in the location, date and path labels. For multiple photos the
+    /// location and date come from the first one.
+    fn show_photo_details(&self, photos: &[&Media], address: Result<String, String>) {
+        let mut location_found = false;
+        let mut date_found = false;
+        let mut caption_found = false;
+
+        let mut photo_date = None;
+        let address_text = address.as_ref().ok().cloned();
+
+        match address {
+            Ok(a) => {
+                location_found = true;
+                self.imp().location_label.set_text(a.as_str());
+            }
+            Err(e) => {
+                self.imp().location_label.set_text("");
+                warn!("Failed to get reverse geocode response, {}", e);
+            }
+        }
+
+        if let Some(Media::Photo { date, location, .. }) = photos.first() {
+            let config = self.imp().config.borrow();
+            // Seasons are flipped south of the equator
+            let southern = location.is_some_and(|(latitude, _)| latitude < 0.0);
+            let string_date = date.and_then(|date| {
+                date.format(config.photo_date_style, &config.photo_date_format, southern)
+            });
+
+            if let Some(string_date) = &string_date {
+                date_found = true;
+                self.imp().photo_date_label.set_text(string_date.as_str());
+            } else {
+                self.imp().photo_date_label.set_text("");
+            }
+            photo_date = string_date;
+        }
+
+        // Pairs and collages show every caption, one per line
+        let captions: Vec<&str> = photos
+            .iter()
+            .filter_map(|photo| match photo {
+                Media::Photo {
+                    caption: Some(caption),
+                    ..
+                } => Some(caption.as_str()),
+                _ => None,
+            })
+            .collect();
+        if captions.is_empty() {
+            self.imp().caption_label.set_text("");
+            self.imp().caption_label.set_visible(false);
+        } else {
+            caption_found = true;
+            self.imp()
+                .caption_label
+                .set_text(captions.join("\n").as_str());
+            self.imp().caption_label.set_visible(true);
+        }
+        let caption = Some(captions.join("\n")).filter(|caption| !caption.is_empty());
+
+        let paths: Vec<&str> = photos
+            .iter()
+            .filter_map(|photo| match photo {
+                Media::Photo { path, .. } => path.to_str(),
+                _ => None,
+            })
+            .collect();
+        self.imp()
+            .photo_location_label
+            .set_text(paths.join("\n").as_str());
+
+        let camera_info: Vec<String> = photos
+            .iter()
+            .filter_map(|photo| match photo {
+                Media::Photo {
+                    camera: Some(camera),
+                    ..
+                } => Some(camera.summary()),
+                _ => None,
+            })
+            .collect();
+        self.imp()
+            .camera_info_label
+            .set_text(camera_info.join("\n\n").as_str());
+        self.update_camera_info();
+        let camera = Some(camera_info.join("\n\n")).filter(|camera| !camera.is_empty());
+
+        let photo = photos
+            .first()
+            .and_then(|photo| photo.path().to_str())
+            .map(|path| path.to_string());
+        update_state(|state| {
+            state.photo = photo.clone();
+            state.date = photo_date.clone();
+            state.location = address_text.clone();
+            state.caption = caption.clone();
+            state.camera = camera.clone();
+        });
+        events::emit(FrameEvent::SlideChanged {
+            photo,
+            date: photo_date,
+            location: address_text,
+            caption,
+            camera,
+        });
+
+        self.imp().location_box.set_visible(
+            (location_found || date_found || caption_found)
+                && self.is_overlay_enabled(OverlayWidget::Location),
+        );
     }
 }